@@ -0,0 +1,332 @@
+//! String forms of Virtio socket addresses and conversion of the various ways
+//! to spell an address into [`VsockAddr`]s.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use crate::{VsockAddr, VMADDR_CID_ANY, VMADDR_CID_HOST, VMADDR_CID_HYPERVISOR};
+
+/// `VMADDR_CID_LOCAL`, which is only exported on Linux and Android.
+const CID_LOCAL: u32 = 1;
+
+/// A Virtio socket address owned by this crate.
+///
+/// It converts to and from [`VsockAddr`], and adds a string form that can be
+/// used in configuration files:
+///
+/// - `vsock://<cid>:<port>`
+/// - `vsock:<cid>:<port>`, as used by systemd socket units
+///
+/// The CID is either a number or one of the symbolic names `hypervisor` (0),
+/// `local` (1), `host` (2) and `any` (`VMADDR_CID_ANY`). Addresses are
+/// displayed in the `vsock://<cid>:<port>` form with a numeric CID.
+///
+/// ```
+/// use tokio_vsock::{VsockEndpoint, VMADDR_CID_HOST};
+///
+/// let endpoint: VsockEndpoint = "vsock://host:8000".parse().unwrap();
+/// assert_eq!(endpoint, VsockEndpoint::new(VMADDR_CID_HOST, 8000));
+/// assert_eq!(endpoint.to_string(), "vsock://2:8000");
+/// assert_eq!("vsock:2:8000".parse::<VsockEndpoint>().unwrap(), endpoint);
+/// ```
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct VsockEndpoint {
+    cid: u32,
+    port: u32,
+}
+
+impl VsockEndpoint {
+    /// Create an endpoint from a CID and a port.
+    pub const fn new(cid: u32, port: u32) -> Self {
+        Self { cid, port }
+    }
+
+    /// The context identifier of this endpoint.
+    pub fn cid(&self) -> u32 {
+        self.cid
+    }
+
+    /// The port of this endpoint.
+    pub fn port(&self) -> u32 {
+        self.port
+    }
+}
+
+impl From<VsockAddr> for VsockEndpoint {
+    fn from(addr: VsockAddr) -> Self {
+        Self::new(addr.cid(), addr.port())
+    }
+}
+
+impl From<VsockEndpoint> for VsockAddr {
+    fn from(endpoint: VsockEndpoint) -> Self {
+        VsockAddr::new(endpoint.cid, endpoint.port)
+    }
+}
+
+impl fmt::Display for VsockEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "vsock://{}:{}", self.cid, self.port)
+    }
+}
+
+impl FromStr for VsockEndpoint {
+    type Err = VsockAddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, port) = split_addr(s)?;
+        let cid = parse_cid(host).ok_or(VsockAddrParseError::InvalidCid)?;
+        Ok(Self::new(cid, port))
+    }
+}
+
+/// Split an address string into its host part and port.
+pub(crate) fn split_addr(s: &str) -> Result<(&str, u32), VsockAddrParseError> {
+    let rest = s
+        .strip_prefix("vsock://")
+        .or_else(|| s.strip_prefix("vsock:"))
+        .ok_or(VsockAddrParseError::InvalidScheme)?;
+    let (host, port) = rest
+        .rsplit_once(':')
+        .ok_or(VsockAddrParseError::MissingPort)?;
+    let port = port.parse().map_err(|_| VsockAddrParseError::InvalidPort)?;
+    Ok((host, port))
+}
+
+/// Parse a numeric or symbolic CID.
+pub(crate) fn parse_cid(s: &str) -> Option<u32> {
+    match s {
+        "hypervisor" => Some(VMADDR_CID_HYPERVISOR),
+        "local" => Some(CID_LOCAL),
+        "host" => Some(VMADDR_CID_HOST),
+        "any" => Some(VMADDR_CID_ANY),
+        _ => s.parse().ok(),
+    }
+}
+
+/// An error returned when parsing a [`VsockEndpoint`] fails.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum VsockAddrParseError {
+    /// The address does not start with `vsock://` or `vsock:`.
+    InvalidScheme,
+    /// The address has no port.
+    MissingPort,
+    /// The CID is neither a number nor a known symbolic name.
+    InvalidCid,
+    /// The port is not a number.
+    InvalidPort,
+}
+
+impl fmt::Display for VsockAddrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VsockAddrParseError::InvalidScheme => "expected a vsock:// or vsock: address",
+            VsockAddrParseError::MissingPort => "missing port in vsock address",
+            VsockAddrParseError::InvalidCid => "invalid CID in vsock address",
+            VsockAddrParseError::InvalidPort => "invalid port in vsock address",
+        })
+    }
+}
+
+impl Error for VsockAddrParseError {}
+
+impl From<VsockAddrParseError> for io::Error {
+    fn from(err: VsockAddrParseError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// A value that can be converted into one or more [`VsockAddr`]s, similar to
+/// [`std::net::ToSocketAddrs`].
+///
+/// Functions taking a `ToVsockAddrs` try each of the addresses in turn until
+/// one of them succeeds.
+///
+/// ```no_run
+/// # async fn connect() -> std::io::Result<()> {
+/// use tokio_vsock::{VsockAddr, VsockStream};
+///
+/// let stream = VsockStream::connect((3, 1024)).await?;
+/// let stream = VsockStream::connect("vsock://host:8000").await?;
+/// let stream = VsockStream::connect(&[VsockAddr::new(3, 1024), VsockAddr::new(4, 1024)][..]).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait ToVsockAddrs {
+    /// Iterator over the addresses this value converts to.
+    type Iter: Iterator<Item = VsockAddr>;
+
+    /// Convert this value into an iterator of addresses.
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter>;
+
+    /// The address strings this value consists of, so that async callers can
+    /// look up the names in them off the runtime.
+    #[doc(hidden)]
+    fn addr_strs(&self) -> Option<Vec<String>> {
+        None
+    }
+}
+
+impl ToVsockAddrs for VsockAddr {
+    type Iter = std::option::IntoIter<VsockAddr>;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        Ok(Some(*self).into_iter())
+    }
+}
+
+impl ToVsockAddrs for VsockEndpoint {
+    type Iter = std::option::IntoIter<VsockAddr>;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        Ok(Some((*self).into()).into_iter())
+    }
+}
+
+impl ToVsockAddrs for (u32, u32) {
+    type Iter = std::option::IntoIter<VsockAddr>;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        Ok(Some(VsockAddr::new(self.0, self.1)).into_iter())
+    }
+}
+
+/// Names in the host part are looked up with the
+/// [default resolver](crate::default_resolver). The async connects do so on
+/// a blocking thread, since resolvers may read files.
+impl ToVsockAddrs for str {
+    type Iter = std::option::IntoIter<VsockAddr>;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        let addr = crate::default_resolver().resolve_addr(self)?;
+        Ok(Some(addr).into_iter())
+    }
+
+    fn addr_strs(&self) -> Option<Vec<String>> {
+        Some(vec![self.to_owned()])
+    }
+}
+
+impl ToVsockAddrs for String {
+    type Iter = std::option::IntoIter<VsockAddr>;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        self.as_str().to_vsock_addrs()
+    }
+
+    fn addr_strs(&self) -> Option<Vec<String>> {
+        self.as_str().addr_strs()
+    }
+}
+
+impl<T: ToVsockAddrs> ToVsockAddrs for [T] {
+    type Iter = std::vec::IntoIter<VsockAddr>;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        let mut addrs = Vec::new();
+        for candidate in self {
+            addrs.extend(candidate.to_vsock_addrs()?);
+        }
+        Ok(addrs.into_iter())
+    }
+
+    fn addr_strs(&self) -> Option<Vec<String>> {
+        let mut strs = Vec::new();
+        for candidate in self {
+            strs.extend(candidate.addr_strs()?);
+        }
+        Some(strs)
+    }
+}
+
+impl<T: ToVsockAddrs, const N: usize> ToVsockAddrs for [T; N] {
+    type Iter = std::vec::IntoIter<VsockAddr>;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        self[..].to_vsock_addrs()
+    }
+
+    fn addr_strs(&self) -> Option<Vec<String>> {
+        self[..].addr_strs()
+    }
+}
+
+impl<T: ToVsockAddrs> ToVsockAddrs for Vec<T> {
+    type Iter = std::vec::IntoIter<VsockAddr>;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        self[..].to_vsock_addrs()
+    }
+
+    fn addr_strs(&self) -> Option<Vec<String>> {
+        self[..].addr_strs()
+    }
+}
+
+impl<T: ToVsockAddrs + ?Sized> ToVsockAddrs for &T {
+    type Iter = T::Iter;
+
+    fn to_vsock_addrs(&self) -> io::Result<Self::Iter> {
+        (**self).to_vsock_addrs()
+    }
+
+    fn addr_strs(&self) -> Option<Vec<String>> {
+        (**self).addr_strs()
+    }
+}
+
+/// The result of converting addresses without blocking.
+pub(crate) enum Lookup {
+    Resolved(Vec<VsockAddr>),
+    /// Address strings naming peers, which the resolver has to look up.
+    Names(Vec<String>),
+}
+
+/// Convert `addrs`, unless that means looking up names with the resolver.
+pub(crate) fn start_lookup<A: ToVsockAddrs>(addrs: &A) -> io::Result<Lookup> {
+    if let Some(strs) = addrs.addr_strs() {
+        let named = |s: &String| split_addr(s).is_ok_and(|(host, _)| parse_cid(host).is_none());
+        if strs.iter().any(named) {
+            return Ok(Lookup::Names(strs));
+        }
+    }
+    Ok(Lookup::Resolved(addrs.to_vsock_addrs()?.collect()))
+}
+
+/// Convert `addrs`, looking up names on Tokio's blocking threads.
+pub(crate) async fn lookup<A: ToVsockAddrs>(addrs: A) -> io::Result<Vec<VsockAddr>> {
+    match start_lookup(&addrs)? {
+        Lookup::Resolved(addrs) => Ok(addrs),
+        Lookup::Names(strs) => {
+            tokio::task::spawn_blocking(move || Ok(strs.to_vsock_addrs()?.collect())).await?
+        }
+    }
+}
+
+/// Run `f` on each address of `addrs` until it succeeds, returning the last
+/// error otherwise.
+pub(crate) fn each_addr<A, F, T>(addrs: A, mut f: F) -> io::Result<T>
+where
+    A: ToVsockAddrs,
+    F: FnMut(VsockAddr) -> io::Result<T>,
+{
+    let mut last_err = None;
+    for addr in addrs.to_vsock_addrs()? {
+        match f(addr) {
+            Ok(value) => return Ok(value),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(no_addresses))
+}
+
+/// The error returned when a [`ToVsockAddrs`] value yields no address.
+pub(crate) fn no_addresses() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "could not resolve to any vsock address",
+    )
+}
@@ -0,0 +1,291 @@
+//! Virtio sockets for runtimes built on [`async-io`], such as smol.
+//!
+//! These types do not need a Tokio reactor. They share the addresses, flags
+//! and socket calls of the Tokio based types, and implement the
+//! [`futures::io`] traits instead of Tokio's:
+//!
+//! ```no_run
+//! use futures::io::{AsyncReadExt, AsyncWriteExt};
+//! use tokio_vsock::async_io::VsockListener;
+//! use tokio_vsock::VMADDR_CID_ANY;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! async_io::block_on(async {
+//!     let listener = VsockListener::bind((VMADDR_CID_ANY, 8000))?;
+//!     loop {
+//!         let (mut stream, _addr) = listener.accept().await?;
+//!         let mut buf = [0u8; 4096];
+//!         let len = stream.read(&mut buf).await?;
+//!         stream.write_all(&buf[..len]).await?;
+//!     }
+//! })
+//! # }
+//! ```
+//!
+//! [`async-io`]: https://docs.rs/async-io
+
+use std::io::{Error, Result, Write};
+use std::net::Shutdown;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use ::async_io::Async;
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::stream::Stream;
+
+use crate::addr::{each_addr, no_addresses, start_lookup, Lookup, ToVsockAddrs};
+use crate::flags::{peer_flags, VsockFlags};
+use crate::sys;
+use crate::{VsockAddr, VsockConnectInfo};
+
+/// A Virtio socket connected to a remote endpoint, driven by async-io.
+#[derive(Debug)]
+pub struct VsockStream {
+    inner: Async<vsock::VsockStream>,
+    info: VsockConnectInfo,
+}
+
+impl VsockStream {
+    /// Register a connected socket with the async-io reactor.
+    pub fn new(connected: vsock::VsockStream) -> Result<Self> {
+        let info = sys::connect_info(&connected);
+        Ok(Self {
+            inner: Async::new(connected)?,
+            info,
+        })
+    }
+
+    /// Open a connection to a remote host.
+    ///
+    /// If `addr` yields several addresses, they are tried in turn until a
+    /// connection succeeds, and the error of the last attempt is returned
+    /// otherwise.
+    pub async fn connect<A: ToVsockAddrs>(addr: A) -> Result<Self> {
+        Self::connect_with_flags(addr, VsockFlags::empty()).await
+    }
+
+    /// Open a connection to a remote host, setting `flags` in the destination
+    /// address, see
+    /// [`VsockStream::connect_with_flags`](crate::VsockStream::connect_with_flags).
+    pub async fn connect_with_flags<A: ToVsockAddrs>(addr: A, flags: VsockFlags) -> Result<Self> {
+        let addrs = match start_lookup(&addr)? {
+            Lookup::Resolved(addrs) => addrs,
+            Lookup::Names(strs) => {
+                // There is no blocking pool without Tokio, so resolve on a
+                // thread of its own.
+                let (tx, rx) = futures::channel::oneshot::channel();
+                std::thread::spawn(move || {
+                    let _ = tx.send(strs.to_vsock_addrs().map(Iterator::collect::<Vec<_>>));
+                });
+                rx.await
+                    .map_err(|_| Error::other("address lookup panicked"))??
+            }
+        };
+        let mut last_err = None;
+        for addr in addrs {
+            match Self::connect_addr(addr, flags).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(no_addresses))
+    }
+
+    async fn connect_addr(addr: VsockAddr, flags: VsockFlags) -> Result<Self> {
+        let mut stream = Self::new(sys::start_connect(addr, flags)?)?;
+        stream.inner.writable().await?;
+        sys::take_error(stream.as_raw_fd())?;
+
+        let local_addr = stream.local_addr().ok();
+        let peer_flags = stream.peer_flags().unwrap_or(flags);
+        stream.info.set_addrs(local_addr, Some(addr), peer_flags);
+        Ok(stream)
+    }
+
+    /// The local address that this socket is bound to.
+    pub fn local_addr(&self) -> Result<VsockAddr> {
+        self.inner.get_ref().local_addr()
+    }
+
+    /// The remote address that this socket is connected to.
+    pub fn peer_addr(&self) -> Result<VsockAddr> {
+        self.inner.get_ref().peer_addr()
+    }
+
+    /// The flags of the remote address, e.g. [`VsockFlags::TO_HOST`] for
+    /// connections forwarded through the host.
+    pub fn peer_flags(&self) -> Result<VsockFlags> {
+        peer_flags(self.as_raw_fd())
+    }
+
+    /// The connection info captured when this connection was accepted or
+    /// established.
+    pub fn connect_info(&self) -> &VsockConnectInfo {
+        &self.info
+    }
+
+    /// Shuts down the read, write, or both halves of this connection.
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.inner.get_ref().shutdown(how)
+    }
+
+    /// Set the `SO_LINGER` option, see
+    /// [`VsockStream::set_linger`](crate::VsockStream::set_linger).
+    pub fn set_linger(&self, duration: Option<Duration>) -> Result<()> {
+        sys::set_linger(self.as_raw_fd(), duration)
+    }
+
+    /// The `SO_LINGER` option, see [`set_linger`](Self::set_linger).
+    pub fn linger(&self) -> Result<Option<Duration>> {
+        sys::linger(self.as_raw_fd())
+    }
+
+    /// The number of written bytes the peer has not received yet, see
+    /// [`VsockStream::unsent_bytes`](crate::VsockStream::unsent_bytes).
+    pub fn unsent_bytes(&self) -> Result<usize> {
+        sys::unsent_bytes(self.as_raw_fd())
+    }
+
+    /// The number of received bytes that can be read without waiting, see
+    /// [`VsockStream::unread_bytes`](crate::VsockStream::unread_bytes).
+    pub fn unread_bytes(&self) -> Result<usize> {
+        sys::unread_bytes(self.as_raw_fd())
+    }
+
+    /// Whether the peer closed the connection or shut down its writing half,
+    /// without consuming any data.
+    pub fn is_read_closed(&self) -> Result<bool> {
+        sys::is_read_closed(self.as_raw_fd())
+    }
+
+    /// Deregister the socket from the reactor, returning it in non-blocking
+    /// mode.
+    pub fn into_inner(self) -> Result<vsock::VsockStream> {
+        self.inner.into_inner()
+    }
+}
+
+impl AsFd for VsockStream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.get_ref().as_fd()
+    }
+}
+
+impl AsRawFd for VsockStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.get_ref().as_raw_fd()
+    }
+}
+
+impl AsyncRead for VsockStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        Pin::new(&mut &*self).poll_read(cx, buf)
+    }
+}
+
+impl AsyncRead for &VsockStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        Pin::new(&mut &self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for VsockStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut &*self).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut &*self).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut &*self).poll_close(cx)
+    }
+}
+
+impl AsyncWrite for &VsockStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut &self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(self.inner.get_ref().flush())
+    }
+
+    /// Shuts down the writing half, like Tokio's `poll_shutdown`.
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(self.shutdown(Shutdown::Write))
+    }
+}
+
+/// A Virtio socket listening for incoming connections, driven by async-io.
+#[derive(Debug)]
+pub struct VsockListener {
+    inner: Async<vsock::VsockListener>,
+}
+
+impl VsockListener {
+    /// Register a bound and listening socket with the async-io reactor.
+    pub fn new(listener: vsock::VsockListener) -> Result<Self> {
+        Ok(Self {
+            inner: Async::new(listener)?,
+        })
+    }
+
+    /// Create a new Virtio socket listener.
+    ///
+    /// If `addr` yields several addresses, the listener is bound to the first
+    /// one that succeeds.
+    pub fn bind<A: ToVsockAddrs>(addr: A) -> Result<Self> {
+        Self::bind_with_flags(addr, VsockFlags::empty())
+    }
+
+    /// Create a new Virtio socket listener, setting `flags` in the local
+    /// address, see
+    /// [`VsockListener::bind_with_flags`](crate::VsockListener::bind_with_flags).
+    pub fn bind_with_flags<A: ToVsockAddrs>(addr: A, flags: VsockFlags) -> Result<Self> {
+        each_addr(addr, |addr| Self::new(sys::bind(addr, flags)?))
+    }
+
+    /// Accepts a new incoming connection to this listener.
+    pub async fn accept(&self) -> Result<(VsockStream, VsockAddr)> {
+        let (stream, addr) = self.inner.read_with(|inner| inner.accept()).await?;
+        Ok((VsockStream::new(stream)?, addr))
+    }
+
+    /// A stream of the sockets this listener accepts.
+    pub fn incoming(&self) -> impl Stream<Item = Result<VsockStream>> + Send + Unpin + '_ {
+        Box::pin(futures::stream::unfold(self, |listener| async move {
+            let stream = listener.accept().await.map(|(stream, _)| stream);
+            Some((stream, listener))
+        }))
+    }
+
+    /// The local address that this listener is bound to.
+    pub fn local_addr(&self) -> Result<VsockAddr> {
+        self.inner.get_ref().local_addr()
+    }
+}
+
+impl AsFd for VsockListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.get_ref().as_fd()
+    }
+}
+
+impl AsRawFd for VsockListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.get_ref().as_raw_fd()
+    }
+}
@@ -9,7 +9,7 @@ impl axum08::serve::Listener for crate::VsockListener {
         loop {
             match std::future::poll_fn(|cx| self.poll_accept(cx)).await {
                 Ok(tuple) => return tuple,
-                Err(err) => handle_accept_error(err).await,
+                Err(err) => crate::server::handle_accept_error(err).await,
             }
         }
     }
@@ -20,26 +20,87 @@ impl axum08::serve::Listener for crate::VsockListener {
 }
 
 #[cfg(feature = "axum08")]
-async fn handle_accept_error(err: std::io::Error) {
-    if matches!(
-        err.kind(),
-        std::io::ErrorKind::ConnectionRefused
-            | std::io::ErrorKind::ConnectionAborted
-            | std::io::ErrorKind::ConnectionReset
-    ) {
-        return;
-    }
-
-    // [From `hyper::Server` in 0.14](https://github.com/hyperium/hyper/blob/v0.14.27/src/server/tcp.rs#L186)
-    //
-    // > A possible scenario is that the process has hit the max open files
-    // > allowed, and so trying to accept a new connection will fail with
-    // > `EMFILE`. In some cases, it's preferable to just wait for some time, if
-    // > the application will likely close some files (or connections), and try
-    // > to accept the connection again. If this option is `true`, the error
-    // > will be logged at the `error` level, since it is still a big deal,
-    // > and then the listener will sleep for 1 second.
-    //
-    // hyper allowed customizing this but axum does not.
-    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+#[cfg_attr(docsrs, doc(cfg(feature = "axum08")))]
+impl axum08::serve::Listener for crate::EitherListener {
+    type Io = crate::EitherStream;
+
+    type Addr = crate::EitherAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            match std::future::poll_fn(|cx| self.poll_accept(cx)).await {
+                Ok(tuple) => return tuple,
+                Err(err) => crate::server::handle_accept_error(err).await,
+            }
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        self.vsock_local_addr().map(crate::EitherAddr::Vsock)
+    }
+}
+
+#[cfg(feature = "axum08")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum08")))]
+impl axum08::serve::Listener for crate::VsockMultiListener {
+    type Io = crate::VsockStream;
+
+    type Addr = vsock::VsockAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            match std::future::poll_fn(|cx| self.poll_accept(cx)).await {
+                Ok(tuple) => return tuple,
+                Err(err) => crate::server::handle_accept_error(err).await,
+            }
+        }
+    }
+
+    /// The address of the first listener; see
+    /// [`local_addrs`](crate::VsockMultiListener::local_addrs) for all of them.
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        self.listeners()[0].local_addr()
+    }
+}
+
+/// Allow `into_make_service_with_connect_info::<VsockConnectInfo>` on services
+/// served from a [`VsockListener`](crate::VsockListener).
+#[cfg(feature = "axum08")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum08")))]
+impl
+    axum08::extract::connect_info::Connected<
+        axum08::serve::IncomingStream<'_, crate::VsockListener>,
+    > for crate::VsockConnectInfo
+{
+    fn connect_info(stream: axum08::serve::IncomingStream<'_, crate::VsockListener>) -> Self {
+        stream.io().connect_info().clone()
+    }
+}
+
+/// Allow `into_make_service_with_connect_info::<VsockConnectInfo>` on services
+/// served from a [`VsockMultiListener`](crate::VsockMultiListener).
+#[cfg(feature = "axum08")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum08")))]
+impl
+    axum08::extract::connect_info::Connected<
+        axum08::serve::IncomingStream<'_, crate::VsockMultiListener>,
+    > for crate::VsockConnectInfo
+{
+    fn connect_info(stream: axum08::serve::IncomingStream<'_, crate::VsockMultiListener>) -> Self {
+        stream.io().connect_info().clone()
+    }
+}
+
+/// Allow `into_make_service_with_connect_info::<EitherConnectInfo>` on services
+/// served from an [`EitherListener`](crate::EitherListener).
+#[cfg(feature = "axum08")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum08")))]
+impl
+    axum08::extract::connect_info::Connected<
+        axum08::serve::IncomingStream<'_, crate::EitherListener>,
+    > for crate::EitherConnectInfo
+{
+    fn connect_info(stream: axum08::serve::IncomingStream<'_, crate::EitherListener>) -> Self {
+        stream.io().connect_info()
+    }
 }
@@ -0,0 +1,516 @@
+#![cfg(feature = "channel")]
+
+//! Typed request/response channels over Virtio socket streams.
+//!
+//! Messages are encoded with an [`RpcEncoding`] and sent in frames of a
+//! [`LengthDelimitedCodec`]. Every request carries an id, so a client can have
+//! many calls in flight on one connection and the server answers them in any
+//! order.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures::{FutureExt, SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, WriteHalf};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{AbortHandle, JoinSet};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use crate::LengthDelimitedCodec;
+
+/// A serialization format for the messages of a channel.
+pub trait RpcEncoding: Clone + Send + Sync + 'static {
+    /// Encode `value` into a message.
+    fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>>;
+
+    /// Decode a message.
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> io::Result<T>;
+}
+
+/// Messages encoded as JSON.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonEncoding;
+
+impl RpcEncoding for JsonEncoding {
+    fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(invalid_data)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> io::Result<T> {
+        serde_json::from_slice(bytes).map_err(invalid_data)
+    }
+}
+
+/// Messages encoded with bincode.
+#[cfg(feature = "bincode")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bincode")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeEncoding;
+
+#[cfg(feature = "bincode")]
+impl RpcEncoding for BincodeEncoding {
+    fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        bincode::serialize(value).map_err(invalid_data)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> io::Result<T> {
+        bincode::deserialize(bytes).map_err(invalid_data)
+    }
+}
+
+/// Messages encoded as CBOR.
+#[cfg(feature = "ciborium")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ciborium")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborEncoding;
+
+#[cfg(feature = "ciborium")]
+impl RpcEncoding for CborEncoding {
+    fn encode<T: Serialize>(&self, value: &T) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes).map_err(invalid_data)?;
+        Ok(bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> io::Result<T> {
+        ciborium::de::from_reader(bytes).map_err(invalid_data)
+    }
+}
+
+fn invalid_data<E: Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// The error of a call made with a [`VsockRpcClient`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RpcError {
+    /// The server handled the request and returned this error.
+    Remote(String),
+    /// The call did not complete before its deadline.
+    DeadlineExceeded,
+    /// The connection closed before the response arrived.
+    Closed,
+    /// The request could not be encoded or sent.
+    Io(io::Error),
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::Remote(msg) => write!(f, "remote error: {}", msg),
+            RpcError::DeadlineExceeded => f.write_str("deadline exceeded"),
+            RpcError::Closed => f.write_str("rpc connection closed"),
+            RpcError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for RpcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RpcError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RpcError {
+    fn from(err: io::Error) -> Self {
+        RpcError::Io(err)
+    }
+}
+
+impl From<RpcError> for io::Error {
+    fn from(err: RpcError) -> Self {
+        match err {
+            RpcError::Io(err) => err,
+            RpcError::DeadlineExceeded => io::Error::new(io::ErrorKind::TimedOut, err),
+            RpcError::Closed => io::Error::new(io::ErrorKind::ConnectionAborted, err),
+            RpcError::Remote(_) => io::Error::other(err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum ClientMessage<Req> {
+    Request {
+        id: u64,
+        /// The time left until the deadline of the call. Clocks of host and
+        /// guest need not agree, so the deadline is sent as a duration.
+        timeout_ms: Option<u64>,
+        body: Req,
+    },
+    Cancel {
+        id: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+enum ServerMessage<Resp> {
+    Response {
+        id: u64,
+        result: Result<Resp, WireError>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+enum WireError {
+    Handler(String),
+    DeadlineExceeded,
+}
+
+impl From<WireError> for RpcError {
+    fn from(err: WireError) -> Self {
+        match err {
+            WireError::Handler(msg) => RpcError::Remote(msg),
+            WireError::DeadlineExceeded => RpcError::DeadlineExceeded,
+        }
+    }
+}
+
+/// Send the frames received on `rx` until all senders are dropped, then
+/// shut the stream down for writing.
+async fn write_frames<W: AsyncWrite>(
+    mut wr: FramedWrite<WriteHalf<W>, LengthDelimitedCodec>,
+    mut rx: mpsc::UnboundedReceiver<Bytes>,
+) -> io::Result<()> {
+    while let Some(frame) = rx.recv().await {
+        wr.feed(frame).await?;
+        // Batch the frames that are already queued.
+        if rx.is_empty() {
+            SinkExt::<Bytes>::flush(&mut wr).await?;
+        }
+    }
+    SinkExt::<Bytes>::close(&mut wr).await
+}
+
+type Framed<T> = (
+    FramedRead<tokio::io::ReadHalf<T>, LengthDelimitedCodec>,
+    FramedWrite<WriteHalf<T>, LengthDelimitedCodec>,
+);
+
+fn framed_halves<T: AsyncRead + AsyncWrite>(io: T) -> Framed<T> {
+    let (rd, wr) = tokio::io::split(io);
+    (
+        FramedRead::new(rd, LengthDelimitedCodec::new()),
+        FramedWrite::new(wr, LengthDelimitedCodec::new()),
+    )
+}
+
+type Pending<Resp> = HashMap<u64, oneshot::Sender<Result<Resp, RpcError>>>;
+
+struct ClientShared<Resp> {
+    /// The calls waiting for a response, `None` once the connection closed.
+    pending: Mutex<Option<Pending<Resp>>>,
+    next_id: AtomicU64,
+}
+
+impl<Resp> ClientShared<Resp> {
+    fn take(&self, id: u64) -> Option<oneshot::Sender<Result<Resp, RpcError>>> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.as_mut().and_then(|pending| pending.remove(&id))
+    }
+}
+
+/// The client end of a typed request/response channel.
+///
+/// The client is cheap to clone, and all clones share the connection. The
+/// connection is closed once the client and all its clones are dropped.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), tokio_vsock::RpcError> {
+/// use tokio_vsock::{JsonEncoding, VsockRpcClient, VsockStream, VMADDR_CID_HOST};
+///
+/// let stream = VsockStream::connect((VMADDR_CID_HOST, 8000)).await?;
+/// let client = VsockRpcClient::<String, usize, _>::new(stream, JsonEncoding);
+/// let len = client.call("hello".to_string()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct VsockRpcClient<Req, Resp, E = JsonEncoding> {
+    shared: Arc<ClientShared<Resp>>,
+    tx: mpsc::UnboundedSender<Bytes>,
+    encoding: E,
+    _request: PhantomData<fn(Req)>,
+}
+
+impl<Req, Resp, E> VsockRpcClient<Req, Resp, E>
+where
+    Req: Serialize,
+    Resp: DeserializeOwned + Send + 'static,
+    E: RpcEncoding,
+{
+    /// Make calls over `io`, usually a [`VsockStream`](crate::VsockStream),
+    /// encoding messages with `encoding`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn new<T>(io: T, encoding: E) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (mut rd, wr) = framed_halves(io);
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_frames(wr, rx));
+
+        let shared = Arc::new(ClientShared {
+            pending: Mutex::new(Some(HashMap::new())),
+            next_id: AtomicU64::new(0),
+        });
+        let reader = shared.clone();
+        let decoder = encoding.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(frame)) = rd.next().await {
+                let ServerMessage::Response { id, result } = match decoder.decode(&frame) {
+                    Ok(message) => message,
+                    Err(_) => break,
+                };
+                if let Some(call) = reader.take(id) {
+                    let _ = call.send(result.map_err(RpcError::from));
+                }
+            }
+            // Dropping the senders fails the calls still waiting.
+            reader
+                .pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take();
+        });
+
+        Self {
+            shared,
+            tx,
+            encoding,
+            _request: PhantomData,
+        }
+    }
+
+    /// Send `request` and wait for its response.
+    ///
+    /// Dropping the returned future cancels the call on the server.
+    pub async fn call(&self, request: Req) -> Result<Resp, RpcError> {
+        self.call_inner(request, None).await
+    }
+
+    /// Send `request` and wait for its response until `deadline`.
+    ///
+    /// The server stops handling the request once the deadline passes.
+    pub async fn call_with_deadline(
+        &self,
+        request: Req,
+        deadline: Instant,
+    ) -> Result<Resp, RpcError> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(timeout, self.call_inner(request, Some(timeout))).await {
+            Ok(result) => result,
+            Err(_) => Err(RpcError::DeadlineExceeded),
+        }
+    }
+
+    async fn call_inner(&self, request: Req, timeout: Option<Duration>) -> Result<Resp, RpcError> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let frame = self.encoding.encode(&ClientMessage::Request {
+            id,
+            timeout_ms: timeout.map(|timeout| timeout.as_millis() as u64),
+            body: &request,
+        })?;
+        let cancel = self.encoding.encode(&ClientMessage::<()>::Cancel { id })?;
+
+        let (response_tx, response_rx) = oneshot::channel();
+        match &mut *self
+            .shared
+            .pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+        {
+            Some(pending) => pending.insert(id, response_tx),
+            None => return Err(RpcError::Closed),
+        };
+        let mut guard = CancelOnDrop {
+            id,
+            shared: &self.shared,
+            tx: &self.tx,
+            cancel: Some(cancel),
+        };
+        if self.tx.send(frame.into()).is_err() {
+            return Err(RpcError::Closed);
+        }
+
+        let result = response_rx.await.unwrap_or(Err(RpcError::Closed));
+        guard.cancel = None;
+        result
+    }
+}
+
+impl<Req, Resp, E: Clone> Clone for VsockRpcClient<Req, Resp, E> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            tx: self.tx.clone(),
+            encoding: self.encoding.clone(),
+            _request: PhantomData,
+        }
+    }
+}
+
+impl<Req, Resp, E: fmt::Debug> fmt::Debug for VsockRpcClient<Req, Resp, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VsockRpcClient")
+            .field("encoding", &self.encoding)
+            .field("closed", &self.tx.is_closed())
+            .finish()
+    }
+}
+
+/// Tells the server to stop handling a call whose caller went away.
+struct CancelOnDrop<'a, Resp> {
+    id: u64,
+    shared: &'a ClientShared<Resp>,
+    tx: &'a mpsc::UnboundedSender<Bytes>,
+    cancel: Option<Vec<u8>>,
+}
+
+impl<Resp> Drop for CancelOnDrop<'_, Resp> {
+    fn drop(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            if self.shared.take(self.id).is_some() {
+                let _ = self.tx.send(cancel.into());
+            }
+        }
+    }
+}
+
+/// The server end of a typed request/response channel.
+///
+/// Each request is handled in its own task, so slow requests do not hold up
+/// the others on the same connection. A handler returning an error sends it
+/// to the client as [`RpcError::Remote`].
+///
+/// ```no_run
+/// # async fn run(stream: tokio_vsock::VsockStream) -> std::io::Result<()> {
+/// use tokio_vsock::{JsonEncoding, VsockRpcServer};
+///
+/// VsockRpcServer::new(JsonEncoding)
+///     .serve(stream, |request: String| async move {
+///         Ok::<_, std::convert::Infallible>(request.len())
+///     })
+///     .await
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VsockRpcServer<E = JsonEncoding> {
+    encoding: E,
+}
+
+impl<E: RpcEncoding> VsockRpcServer<E> {
+    /// Create a server decoding messages with `encoding`.
+    pub fn new(encoding: E) -> Self {
+        Self { encoding }
+    }
+
+    /// Answer the requests received on `io`, usually a
+    /// [`VsockStream`](crate::VsockStream), with `handler` until the client
+    /// closes the connection.
+    ///
+    /// Requests still being handled when the client goes away are completed
+    /// before this returns. Fails if a message cannot be decoded.
+    pub async fn serve<T, Req, Resp, Err, F, Fut>(&self, io: T, handler: F) -> io::Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+        Req: DeserializeOwned + Send + 'static,
+        Resp: Serialize + Send + 'static,
+        Err: fmt::Display,
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Resp, Err>> + Send + 'static,
+    {
+        let (mut rd, wr) = framed_halves(io);
+        let (tx, rx) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write_frames(wr, rx));
+
+        let handler = Arc::new(handler);
+        let mut tasks = JoinSet::new();
+        let mut in_flight: HashMap<u64, AbortHandle> = HashMap::new();
+        let result = loop {
+            let frame = match rd.next().await {
+                Some(Ok(frame)) => frame,
+                Some(Err(err)) => break Err(err),
+                None => break Ok(()),
+            };
+            while let Some(done) = tasks.try_join_next() {
+                if let Ok(id) = done {
+                    in_flight.remove(&id);
+                }
+            }
+
+            match self.encoding.decode(&frame) {
+                Ok(ClientMessage::Request {
+                    id,
+                    timeout_ms,
+                    body,
+                }) => {
+                    let handler = handler.clone();
+                    let encoding = self.encoding.clone();
+                    let tx = tx.clone();
+                    let abort = tasks.spawn(async move {
+                        // A panicking handler fails its request instead of leaving
+                        // the client waiting.
+                        let response = AssertUnwindSafe(handler(body)).catch_unwind();
+                        let result = match timeout_ms {
+                            Some(ms) => {
+                                tokio::time::timeout(Duration::from_millis(ms), response).await
+                            }
+                            None => Ok(response.await),
+                        };
+                        let result = match result {
+                            Ok(Ok(Ok(resp))) => Ok(resp),
+                            Ok(Ok(Err(err))) => Err(WireError::Handler(err.to_string())),
+                            Ok(Err(_panic)) => Err(WireError::Handler("handler panicked".into())),
+                            Err(_) => Err(WireError::DeadlineExceeded),
+                        };
+                        let frame = encoding
+                            .encode(&ServerMessage::Response { id, result })
+                            .or_else(|err| {
+                                let result = Err(WireError::Handler(format!(
+                                    "failed to encode response: {}",
+                                    err
+                                )));
+                                encoding.encode(&ServerMessage::<Resp>::Response { id, result })
+                            });
+                        if let Ok(frame) = frame {
+                            let _ = tx.send(frame.into());
+                        }
+                        id
+                    });
+                    in_flight.insert(id, abort);
+                }
+                Ok(ClientMessage::Cancel { id }) => {
+                    if let Some(abort) = in_flight.remove(&id) {
+                        abort.abort();
+                    }
+                }
+                Err(err) => break Err(err),
+            }
+        };
+
+        if result.is_ok() {
+            while tasks.join_next().await.is_some() {}
+        }
+        tasks.shutdown().await;
+        drop(tx);
+        let written = writer.await.map_err(io::Error::other)?;
+        result.and(written)
+    }
+}
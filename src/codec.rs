@@ -0,0 +1,62 @@
+#![cfg(feature = "codec")]
+
+//! Framing of Virtio socket streams, for message based control channels.
+
+use tokio_util::codec::{Framed, FramedRead, FramedWrite};
+
+use crate::{OwnedReadHalf, OwnedWriteHalf, VsockStream};
+
+/// A codec for frames prefixed with their length, re-exported from
+/// `tokio_util`.
+///
+/// By default the length is a 4 byte big-endian integer and frames are at
+/// most 8 MiB long. Decoding yields the frames without their header as
+/// `BytesMut`, encoding accepts `Bytes`. Other layouts are set up with
+/// [`LengthDelimitedCodec::builder`]:
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use bytes::Bytes;
+/// use futures::{SinkExt, StreamExt};
+/// use tokio_vsock::{LengthDelimitedCodec, VsockStream, VMADDR_CID_HOST};
+///
+/// let stream = VsockStream::connect((VMADDR_CID_HOST, 8000)).await?;
+/// let codec = LengthDelimitedCodec::builder()
+///     .length_field_length(2)
+///     .new_codec();
+/// let mut framed = stream.framed(codec);
+///
+/// framed.send(Bytes::from_static(b"status")).await?;
+/// if let Some(reply) = framed.next().await {
+///     println!("{:?}", reply?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub use tokio_util::codec::LengthDelimitedCodec;
+
+impl VsockStream {
+    /// Wrap this stream into a [`Framed`] reading and writing frames with
+    /// `codec`, e.g. a [`LengthDelimitedCodec`].
+    #[cfg_attr(docsrs, doc(cfg(feature = "codec")))]
+    pub fn framed<C>(self, codec: C) -> Framed<VsockStream, C> {
+        Framed::new(self, codec)
+    }
+
+    /// Split this stream into a reading and a writing half, each framed with
+    /// a copy of `codec`.
+    ///
+    /// The halves can be moved to different tasks, and reunited with
+    /// [`OwnedReadHalf::unsplit`] after taking them out of the framing.
+    #[cfg_attr(docsrs, doc(cfg(feature = "codec")))]
+    pub fn into_framed_halves<C: Clone>(
+        self,
+        codec: C,
+    ) -> (FramedRead<OwnedReadHalf, C>, FramedWrite<OwnedWriteHalf, C>) {
+        let (rd, wr) = self.into_split();
+        (
+            FramedRead::new(rd, codec.clone()),
+            FramedWrite::new(wr, codec),
+        )
+    }
+}
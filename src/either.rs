@@ -0,0 +1,210 @@
+//! A listener accepting connections from a Virtio socket and a TCP (and
+//! optionally a Unix domain) socket at the same time.
+//!
+//! This is useful to expose the same service to virtual machines over vsock and
+//! to local clients over TCP, e.g. for debugging.
+
+use std::fmt;
+use std::io::Result;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+use futures::{future::poll_fn, ready, stream::Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{unix, TcpListener, TcpStream, UnixListener, UnixStream};
+
+use crate::{VsockAddr, VsockConnectInfo, VsockEndpoint, VsockListener, VsockStream};
+
+/// A listener accepting connections from a [`VsockListener`], a
+/// [`TcpListener`] and optionally a [`UnixListener`].
+///
+/// Accepting polls the listeners in turn so that a busy listener cannot starve
+/// the others.
+#[derive(Debug)]
+pub struct EitherListener {
+    vsock: VsockListener,
+    tcp: TcpListener,
+    unix: Option<UnixListener>,
+    next: AtomicUsize,
+}
+
+impl EitherListener {
+    /// Create a listener accepting from both `vsock` and `tcp`.
+    pub fn new(vsock: VsockListener, tcp: TcpListener) -> Self {
+        Self {
+            vsock,
+            tcp,
+            unix: None,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Also accept connections from `unix`.
+    pub fn with_unix(mut self, unix: UnixListener) -> Self {
+        self.unix = Some(unix);
+        self
+    }
+
+    /// Accepts a new incoming connection from any of the listeners.
+    pub async fn accept(&self) -> Result<(EitherStream, EitherAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// Attempt to accept a connection from any of the listeners.
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(EitherStream, EitherAddr)>> {
+        let count = if self.unix.is_some() { 3 } else { 2 };
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        for i in 0..count {
+            let accepted = match (start + i) % count {
+                0 => self.vsock.poll_accept(cx).map_ok(|(stream, addr)| {
+                    (EitherStream::Vsock(stream), EitherAddr::Vsock(addr))
+                }),
+                1 => self
+                    .tcp
+                    .poll_accept(cx)
+                    .map_ok(|(stream, addr)| (EitherStream::Tcp(stream), EitherAddr::Tcp(addr))),
+                _ => match &self.unix {
+                    Some(unix) => unix.poll_accept(cx).map_ok(|(stream, addr)| {
+                        (EitherStream::Unix(stream), EitherAddr::Unix(addr))
+                    }),
+                    None => Poll::Pending,
+                },
+            };
+            if accepted.is_ready() {
+                return accepted;
+            }
+        }
+
+        Poll::Pending
+    }
+
+    /// The local address of the Virtio socket listener.
+    pub fn vsock_local_addr(&self) -> Result<VsockAddr> {
+        self.vsock.local_addr()
+    }
+
+    /// The local address of the TCP listener.
+    pub fn tcp_local_addr(&self) -> Result<SocketAddr> {
+        self.tcp.local_addr()
+    }
+
+    /// The local address of the Unix domain socket listener, if any.
+    pub fn unix_local_addr(&self) -> Option<Result<unix::SocketAddr>> {
+        self.unix.as_ref().map(UnixListener::local_addr)
+    }
+
+    /// Consumes this listener, returning a stream of the sockets it accepts.
+    pub fn incoming(self) -> EitherIncoming {
+        EitherIncoming { inner: self }
+    }
+}
+
+/// Stream returned by [`EitherListener::incoming`].
+#[derive(Debug)]
+pub struct EitherIncoming {
+    inner: EitherListener,
+}
+
+impl Stream for EitherIncoming {
+    type Item = Result<EitherStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let (socket, _) = ready!(self.inner.poll_accept(cx))?;
+        Poll::Ready(Some(Ok(socket)))
+    }
+}
+
+/// The address of a peer accepted by an [`EitherListener`].
+#[derive(Debug, Clone)]
+pub enum EitherAddr {
+    Vsock(VsockAddr),
+    Tcp(SocketAddr),
+    Unix(unix::SocketAddr),
+}
+
+impl fmt::Display for EitherAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EitherAddr::Vsock(addr) => VsockEndpoint::from(*addr).fmt(f),
+            EitherAddr::Tcp(addr) => write!(f, "tcp://{}", addr),
+            EitherAddr::Unix(addr) => match addr.as_pathname() {
+                Some(path) => write!(f, "unix://{}", path.display()),
+                None => f.write_str("unix://(unnamed)"),
+            },
+        }
+    }
+}
+
+/// Connection info for an [`EitherStream`].
+#[derive(Debug, Clone)]
+pub enum EitherConnectInfo {
+    Vsock(VsockConnectInfo),
+    Tcp {
+        local_addr: Option<SocketAddr>,
+        remote_addr: Option<SocketAddr>,
+    },
+    Unix {
+        peer_addr: Option<unix::SocketAddr>,
+    },
+}
+
+/// A connection accepted by an [`EitherListener`].
+#[derive(Debug)]
+pub enum EitherStream {
+    Vsock(VsockStream),
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl EitherStream {
+    /// Return the connection info for this stream.
+    pub fn connect_info(&self) -> EitherConnectInfo {
+        match self {
+            EitherStream::Vsock(stream) => EitherConnectInfo::Vsock(stream.connect_info().clone()),
+            EitherStream::Tcp(stream) => EitherConnectInfo::Tcp {
+                local_addr: stream.local_addr().ok(),
+                remote_addr: stream.peer_addr().ok(),
+            },
+            EitherStream::Unix(stream) => EitherConnectInfo::Unix {
+                peer_addr: stream.peer_addr().ok(),
+            },
+        }
+    }
+}
+
+macro_rules! delegate {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        match $self.get_mut() {
+            EitherStream::Vsock(stream) => Pin::new(stream).$method($($arg),*),
+            EitherStream::Tcp(stream) => Pin::new(stream).$method($($arg),*),
+            EitherStream::Unix(stream) => Pin::new(stream).$method($($arg),*),
+        }
+    };
+}
+
+impl AsyncRead for EitherStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        delegate!(self.poll_read(cx, buf))
+    }
+}
+
+impl AsyncWrite for EitherStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        delegate!(self.poll_write(cx, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        delegate!(self.poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        delegate!(self.poll_shutdown(cx))
+    }
+}
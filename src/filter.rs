@@ -0,0 +1,127 @@
+//! Accept filters restricting which peers may connect to a [`VsockListener`]
+//! and how many connections they may keep open.
+//!
+//! [`VsockListener`]: crate::VsockListener
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{SharedPeerPolicy, VsockAddr};
+
+#[derive(Debug, Default)]
+struct Connections {
+    total: usize,
+    per_cid: HashMap<u32, usize>,
+}
+
+/// Decides which connections a [`VsockListener`](crate::VsockListener) accepts.
+///
+/// Connections from peers rejected by the policy, or that would exceed one of the
+/// connection limits, are closed as soon as they are accepted and never handed
+/// to the caller. Accepted connections count towards the limits until the
+/// [`VsockStream`](crate::VsockStream) (or both of its owned halves) is dropped.
+///
+/// Clones share the same connection counts, so a clone can be kept around to
+/// inspect them while the listener is in use.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptFilter {
+    policy: Option<SharedPeerPolicy>,
+    max_connections: Option<usize>,
+    max_connections_per_cid: Option<usize>,
+    connections: Arc<Mutex<Connections>>,
+}
+
+impl AcceptFilter {
+    /// Create a filter that accepts every connection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accept connections from peers allowed by `policy`.
+    pub fn policy(mut self, policy: impl Into<SharedPeerPolicy>) -> Self {
+        self.policy = Some(policy.into());
+        self
+    }
+
+    /// Limit the number of connections open at the same time.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Limit the number of connections each peer CID may keep open at the
+    /// same time.
+    pub fn max_connections_per_cid(mut self, max: usize) -> Self {
+        self.max_connections_per_cid = Some(max);
+        self
+    }
+
+    /// The number of accepted connections that are still open.
+    pub fn active_connections(&self) -> usize {
+        self.lock().total
+    }
+
+    /// The number of accepted connections from `cid` that are still open.
+    pub fn active_connections_for(&self, cid: u32) -> usize {
+        self.lock().per_cid.get(&cid).copied().unwrap_or(0)
+    }
+
+    /// Decide whether a connection from `peer` is accepted, reserving a slot
+    /// for it if so.
+    pub(crate) fn admit(&self, peer: &VsockAddr) -> Option<ConnectionSlot> {
+        if let Some(policy) = &self.policy {
+            if !policy.is_allowed(peer) {
+                return None;
+            }
+        }
+
+        let mut connections = self.lock();
+        if matches!(self.max_connections, Some(max) if connections.total >= max) {
+            return None;
+        }
+        let per_cid = connections.per_cid.get(&peer.cid()).copied().unwrap_or(0);
+        if matches!(self.max_connections_per_cid, Some(max) if per_cid >= max) {
+            return None;
+        }
+        // Only admitted peers get an entry, so rejected CIDs do not pile up.
+        connections.per_cid.insert(peer.cid(), per_cid + 1);
+        connections.total += 1;
+
+        Some(ConnectionSlot {
+            cid: peer.cid(),
+            connections: self.connections.clone(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connections> {
+        self.connections.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A connection counted by an [`AcceptFilter`], released when dropped.
+pub(crate) struct ConnectionSlot {
+    cid: u32,
+    connections: Arc<Mutex<Connections>>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+        connections.total -= 1;
+        if let Some(count) = connections.per_cid.get_mut(&self.cid) {
+            *count -= 1;
+            if *count == 0 {
+                connections.per_cid.remove(&self.cid);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ConnectionSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionSlot")
+            .field("cid", &self.cid)
+            .finish()
+    }
+}
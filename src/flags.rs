@@ -0,0 +1,176 @@
+//! Support for the `svm_flags` field of `sockaddr_vm`.
+
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::mem::{self, size_of};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::OnceLock;
+
+use libc::{sockaddr, sockaddr_vm, socklen_t};
+
+use crate::{VsockAddr, VMADDR_CID_ANY};
+
+/// Flags carried in the `svm_flags` field of a Virtio socket address.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub struct VsockFlags(u8);
+
+impl VsockFlags {
+    /// Route the connection to the host, even if the destination CID is that
+    /// of another guest (`VMADDR_FLAG_TO_HOST`).
+    ///
+    /// In nested virtualization this lets a guest that also hosts guests of its
+    /// own reach its parent's siblings through its parent. Accepted connections
+    /// carry this flag when they were forwarded this way.
+    pub const TO_HOST: VsockFlags = VsockFlags(0x01);
+
+    /// No flags.
+    pub const fn empty() -> Self {
+        VsockFlags(0)
+    }
+
+    /// Flags from their raw representation.
+    pub const fn from_bits(bits: u8) -> Self {
+        VsockFlags(bits)
+    }
+
+    /// The raw representation of the flags.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether no flag is set.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether all flags of `other` are set.
+    pub const fn contains(self, other: VsockFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for VsockFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        VsockFlags(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Debug for VsockFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.contains(VsockFlags::TO_HOST) {
+            write!(f, "VsockFlags(TO_HOST")?;
+            if self.0 != VsockFlags::TO_HOST.0 {
+                write!(f, " | {:#x}", self.0 & !VsockFlags::TO_HOST.0)?;
+            }
+            f.write_str(")")
+        } else {
+            write!(f, "VsockFlags({:#x})", self.0)
+        }
+    }
+}
+
+/// Whether the running kernel supports [`VsockFlags::TO_HOST`].
+///
+/// Support was added in Linux 5.10; older kernels reject addresses with flags.
+/// The result is probed once by binding a throwaway socket and then cached.
+pub fn supports_flag_to_host() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+
+    *SUPPORTED.get_or_init(|| {
+        let fd = match new_socket() {
+            Ok(fd) => fd,
+            Err(_) => return false,
+        };
+        let addr = VsockAddr::new(VMADDR_CID_ANY, libc::VMADDR_PORT_ANY);
+        sockaddr_vm(addr, VsockFlags::TO_HOST)
+            .map(|addr| unsafe { bind_raw(fd.as_raw_fd(), &addr) } == 0)
+            .unwrap_or(false)
+    })
+}
+
+/// Build the `sockaddr_vm` for `addr` with `flags` set.
+pub(crate) fn sockaddr_vm(addr: VsockAddr, flags: VsockFlags) -> Result<sockaddr_vm> {
+    let mut raw: sockaddr_vm = *addr.as_ref();
+    if !flags.is_empty() {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            // `svm_flags` is the first byte of what libc still calls `svm_zero`.
+            raw.svm_zero[0] = flags.bits();
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "vsock address flags are not supported on this platform",
+            ));
+        }
+    }
+    Ok(raw)
+}
+
+/// The flags of the address `fd` is connected to.
+pub(crate) fn peer_flags(fd: RawFd) -> Result<VsockFlags> {
+    let mut raw: sockaddr_vm = unsafe { mem::zeroed() };
+    let mut len = size_of::<sockaddr_vm>() as socklen_t;
+    if unsafe { libc::getpeername(fd, &mut raw as *mut _ as *mut sockaddr, &mut len) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        Ok(VsockFlags(raw.svm_zero[0]))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        Ok(VsockFlags::empty())
+    }
+}
+
+/// Map the `EINVAL` older kernels return for addresses with flags to a
+/// clearer error.
+pub(crate) fn map_flags_error(err: Error, flags: VsockFlags) -> Error {
+    if !flags.is_empty() && err.raw_os_error() == Some(libc::EINVAL) && !supports_flag_to_host() {
+        Error::new(
+            ErrorKind::Unsupported,
+            "the kernel does not support vsock address flags",
+        )
+    } else {
+        err
+    }
+}
+
+unsafe fn bind_raw(fd: RawFd, addr: &sockaddr_vm) -> libc::c_int {
+    libc::bind(
+        fd,
+        addr as *const _ as *const sockaddr,
+        size_of::<sockaddr_vm>() as socklen_t,
+    )
+}
+
+fn new_socket() -> Result<OwnedFd> {
+    let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM, 0) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Create a listening socket bound to `addr`.
+pub(crate) fn bind_listener(addr: VsockAddr, flags: VsockFlags) -> Result<vsock::VsockListener> {
+    let raw = sockaddr_vm(addr, flags)?;
+    let fd = new_socket()?;
+    if unsafe { bind_raw(fd.as_raw_fd(), &raw) } < 0 {
+        return Err(map_flags_error(Error::last_os_error(), flags));
+    }
+    // Same backlog as the Rust standard library.
+    if unsafe { libc::listen(fd.as_raw_fd(), 128) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(vsock::VsockListener::from(fd))
+}
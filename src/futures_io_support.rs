@@ -0,0 +1,67 @@
+//! The [`futures::io`] traits for streams and their halves, delegating to
+//! their Tokio implementations.
+
+use std::io::Result;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::ready;
+use tokio::io::ReadBuf;
+
+use crate::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, VsockStream, WriteHalf};
+
+fn poll_read<R: tokio::io::AsyncRead + ?Sized>(
+    reader: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<Result<usize>> {
+    let mut buf = ReadBuf::new(buf);
+    ready!(reader.poll_read(cx, &mut buf))?;
+    Poll::Ready(Ok(buf.filled().len()))
+}
+
+macro_rules! impl_async_read {
+    ($($ty:ty),*) => {
+        $(
+            #[cfg_attr(docsrs, doc(cfg(feature = "futures-io")))]
+            impl futures::io::AsyncRead for $ty {
+                fn poll_read(
+                    self: Pin<&mut Self>,
+                    cx: &mut Context<'_>,
+                    buf: &mut [u8],
+                ) -> Poll<Result<usize>> {
+                    poll_read(self, cx, buf)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_async_write {
+    ($($ty:ty),*) => {
+        $(
+            #[cfg_attr(docsrs, doc(cfg(feature = "futures-io")))]
+            impl futures::io::AsyncWrite for $ty {
+                fn poll_write(
+                    self: Pin<&mut Self>,
+                    cx: &mut Context<'_>,
+                    buf: &[u8],
+                ) -> Poll<Result<usize>> {
+                    tokio::io::AsyncWrite::poll_write(self, cx, buf)
+                }
+
+                fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                    tokio::io::AsyncWrite::poll_flush(self, cx)
+                }
+
+                /// Shuts down the writing half, like Tokio's `poll_shutdown`.
+                fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+                    tokio::io::AsyncWrite::poll_shutdown(self, cx)
+                }
+            }
+        )*
+    };
+}
+
+impl_async_read!(VsockStream, OwnedReadHalf, ReadHalf<'_>);
+impl_async_write!(VsockStream, OwnedWriteHalf, WriteHalf<'_>);
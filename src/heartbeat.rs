@@ -0,0 +1,348 @@
+//! Detection of dead peers by exchanging heartbeats over a stream.
+//!
+//! Virtio sockets have no TCP keepalive, so a read from a guest that was paused
+//! or killed can wait forever. A [`HeartbeatStream`] sends a ping every
+//! interval, answers the pings of its peer and fails reads and writes with
+//! [`PeerDead`] once too many pings in a row went unanswered. Both ends of the
+//! connection have to use a `HeartbeatStream`, since data is sent in frames so
+//! that pings can be interleaved with it.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Result};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::{Buf, BufMut, BytesMut};
+use futures::ready;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+use crate::VsockStream;
+
+const FRAME_DATA: u8 = 0;
+const FRAME_PING: u8 = 1;
+const FRAME_PONG: u8 = 2;
+
+const HEADER_LEN: usize = 5;
+const NONCE_LEN: usize = 8;
+
+/// The largest data frame sent, and the amount of buffered output beyond
+/// which writes wait for the stream to drain.
+const MAX_FRAME: usize = 64 * 1024;
+
+/// The error returned once the peer missed too many heartbeats.
+///
+/// It is wrapped in an [`io::Error`] of kind
+/// [`TimedOut`](io::ErrorKind::TimedOut):
+///
+/// ```
+/// # fn check(err: std::io::Error) {
+/// use tokio_vsock::PeerDead;
+///
+/// if let Some(dead) = err.get_ref().and_then(|e| e.downcast_ref::<PeerDead>()) {
+///     eprintln!("peer missed {} heartbeats", dead.missed());
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PeerDead {
+    missed: u32,
+}
+
+impl PeerDead {
+    /// The number of heartbeats the peer missed in a row.
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+impl fmt::Display for PeerDead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "peer missed {} heartbeats in a row", self.missed)
+    }
+}
+
+impl Error for PeerDead {}
+
+#[derive(Debug, Default)]
+struct Stats {
+    /// The last round-trip time in microseconds, 0 if none was measured yet.
+    rtt_micros: AtomicU64,
+    missed: AtomicU32,
+}
+
+/// A handle to the heartbeat statistics of a [`HeartbeatStream`], usable while
+/// the stream itself is busy reading or writing.
+#[derive(Debug, Clone)]
+pub struct HeartbeatHandle {
+    stats: Arc<Stats>,
+}
+
+impl HeartbeatHandle {
+    /// The round-trip time of the last answered heartbeat.
+    pub fn last_rtt(&self) -> Option<Duration> {
+        match self.stats.rtt_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    /// The number of heartbeats the peer missed since it was last heard from.
+    pub fn missed_heartbeats(&self) -> u32 {
+        self.stats.missed.load(Ordering::Relaxed)
+    }
+}
+
+/// A stream exchanging heartbeats with its peer.
+///
+/// Heartbeats are only sent, answered and checked while the stream is polled,
+/// so keep a read pending on both ends, or pick an interval and
+/// [`max_missed`](Self::max_missed) longer than the pauses in between. Written data is buffered and
+/// sent in frames; use [`flush`](tokio::io::AsyncWriteExt::flush) to make sure
+/// it went out.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use std::time::Duration;
+/// use tokio::io::AsyncReadExt;
+/// use tokio_vsock::{HeartbeatStream, VsockStream};
+///
+/// let stream = VsockStream::connect((3, 8000)).await?;
+/// let mut stream = HeartbeatStream::new(stream, Duration::from_secs(1)).max_missed(3);
+/// let handle = stream.handle();
+///
+/// let mut buf = [0u8; 1024];
+/// // Fails within about three seconds if the guest stops answering.
+/// let len = stream.read(&mut buf).await?;
+/// println!("read {} bytes, rtt {:?}", len, handle.last_rtt());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct HeartbeatStream<T = VsockStream> {
+    inner: T,
+    interval: Interval,
+    max_missed: u32,
+    /// The nonce and send time of the ping waiting for a pong.
+    outstanding: Option<(u64, Instant)>,
+    next_nonce: u64,
+    missed: u32,
+    stats: Arc<Stats>,
+    rbuf: BytesMut,
+    /// Bytes of the current data frame not read from `rbuf` yet.
+    data_left: usize,
+    wbuf: BytesMut,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> HeartbeatStream<T> {
+    /// Wrap `inner`, sending a heartbeat every `interval`.
+    ///
+    /// The peer is considered dead after missing 3 heartbeats in a row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime or if `interval` is zero.
+    pub fn new(inner: T, interval: Duration) -> Self {
+        let mut interval = tokio::time::interval_at(Instant::now() + interval, interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            inner,
+            interval,
+            max_missed: 3,
+            outstanding: None,
+            next_nonce: 0,
+            missed: 0,
+            stats: Arc::default(),
+            rbuf: BytesMut::new(),
+            data_left: 0,
+            wbuf: BytesMut::new(),
+        }
+    }
+
+    /// Consider the peer dead after it missed `max` heartbeats in a row.
+    pub fn max_missed(mut self, max: u32) -> Self {
+        self.max_missed = max.max(1);
+        self
+    }
+
+    /// Return a handle to the heartbeat statistics of this stream.
+    pub fn handle(&self) -> HeartbeatHandle {
+        HeartbeatHandle {
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// The round-trip time of the last answered heartbeat.
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.handle().last_rtt()
+    }
+
+    /// Return a reference to the wrapped stream.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Return the wrapped stream. Buffered data is lost.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn queue(&mut self, ty: u8, body: &[u8]) {
+        self.wbuf.reserve(HEADER_LEN + body.len());
+        self.wbuf.put_u8(ty);
+        self.wbuf.put_u32(body.len() as u32);
+        self.wbuf.put_slice(body);
+    }
+
+    /// Send a ping if one is due, and fail once the peer missed too many.
+    fn poll_heartbeat(&mut self, cx: &mut Context<'_>) -> Result<()> {
+        while self.interval.poll_tick(cx).is_ready() {
+            if self.outstanding.is_some() {
+                self.missed += 1;
+                self.stats.missed.store(self.missed, Ordering::Relaxed);
+            }
+            if self.missed >= self.max_missed {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    PeerDead {
+                        missed: self.missed,
+                    },
+                ));
+            }
+            let nonce = self.next_nonce;
+            self.next_nonce = nonce.wrapping_add(1);
+            self.outstanding = Some((nonce, Instant::now()));
+            self.queue(FRAME_PING, &nonce.to_be_bytes());
+        }
+        // Control frames go out on a best effort basis, data waits for a flush.
+        match self.poll_write_buf(cx) {
+            Poll::Ready(Err(err)) => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while !self.wbuf.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.wbuf))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.wbuf.advance(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn heard_from_peer(&mut self) {
+        self.missed = 0;
+        self.stats.missed.store(0, Ordering::Relaxed);
+    }
+
+    /// Handle the control frames and headers at the start of `rbuf`, stopping
+    /// at data or at an incomplete frame.
+    fn process_frames(&mut self) -> Result<()> {
+        while self.data_left == 0 && self.rbuf.len() >= HEADER_LEN {
+            let ty = self.rbuf[0];
+            let len = u32::from_be_bytes([self.rbuf[1], self.rbuf[2], self.rbuf[3], self.rbuf[4]])
+                as usize;
+            match ty {
+                FRAME_DATA => {
+                    self.rbuf.advance(HEADER_LEN);
+                    self.data_left = len;
+                }
+                FRAME_PING | FRAME_PONG if len == NONCE_LEN => {
+                    if self.rbuf.len() < HEADER_LEN + NONCE_LEN {
+                        break;
+                    }
+                    self.rbuf.advance(HEADER_LEN);
+                    let nonce = self.rbuf.get_u64();
+                    if ty == FRAME_PING {
+                        self.queue(FRAME_PONG, &nonce.to_be_bytes());
+                    } else if let Some((expected, sent_at)) = self.outstanding {
+                        if nonce == expected {
+                            let rtt = sent_at.elapsed().as_micros().max(1) as u64;
+                            self.stats.rtt_micros.store(rtt, Ordering::Relaxed);
+                            self.outstanding = None;
+                        }
+                    }
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid heartbeat frame",
+                    ))
+                }
+            }
+            self.heard_from_peer();
+        }
+        Ok(())
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for HeartbeatStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        loop {
+            this.process_frames()?;
+            this.poll_heartbeat(cx)?;
+
+            if this.data_left > 0 && !this.rbuf.is_empty() {
+                let n = this.data_left.min(this.rbuf.len()).min(buf.remaining());
+                buf.put_slice(&this.rbuf[..n]);
+                this.rbuf.advance(n);
+                this.data_left -= n;
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0u8; 8 * 1024];
+            let mut chunk = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            if chunk.filled().is_empty() {
+                if this.data_left > 0 || !this.rbuf.is_empty() {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                return Poll::Ready(Ok(()));
+            }
+            this.rbuf.extend_from_slice(chunk.filled());
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for HeartbeatStream<T> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        this.poll_heartbeat(cx)?;
+        if this.wbuf.len() >= MAX_FRAME {
+            ready!(this.poll_write_buf(cx))?;
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let n = buf.len().min(MAX_FRAME);
+        this.queue(FRAME_DATA, &buf[..n]);
+        if let Poll::Ready(Err(err)) = this.poll_write_buf(cx) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        this.poll_heartbeat(cx)?;
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,279 @@
+//! A stream of accepted connections with graceful shutdown support, intended
+//! to be handed to tonic's `serve_with_incoming`.
+
+use std::fmt;
+use std::future::Future;
+use std::io::Result;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{ready, stream::Stream, task::AtomicWaker};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{watch, AcquireError, OwnedSemaphorePermit, Semaphore};
+
+use crate::{VsockListener, VsockStream};
+
+type AcquireFuture =
+    Pin<Box<dyn Future<Output = std::result::Result<OwnedSemaphorePermit, AcquireError>> + Send>>;
+
+/// State shared between a [`VsockIncoming`], its handles and the streams it yields.
+struct Shared {
+    shutdown: AtomicBool,
+    waker: AtomicWaker,
+    active: watch::Sender<usize>,
+}
+
+impl Shared {
+    fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.waker.wake();
+    }
+}
+
+/// Stream of connections accepted from a [`VsockListener`].
+///
+/// Compared to [`Incoming`](crate::Incoming), this stream can cap the number of
+/// connections open at the same time, ends once a shutdown is requested and
+/// keeps track of the connections it handed out so that they can be drained.
+/// The yielded [`VsockIncomingStream`]s implement tonic's `Connected`, so the
+/// stream can be passed to `serve_with_incoming` directly.
+pub struct VsockIncoming {
+    listener: VsockListener,
+    limit: Option<Arc<Semaphore>>,
+    acquire: Option<AcquireFuture>,
+    permit: Option<OwnedSemaphorePermit>,
+    signal: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    shared: Arc<Shared>,
+}
+
+impl VsockIncoming {
+    /// Create a new incoming stream accepting connections from `listener`.
+    pub fn new(listener: VsockListener) -> Self {
+        let (active, _) = watch::channel(0);
+        Self {
+            listener,
+            limit: None,
+            acquire: None,
+            permit: None,
+            signal: None,
+            shared: Arc::new(Shared {
+                shutdown: AtomicBool::new(false),
+                waker: AtomicWaker::new(),
+                active,
+            }),
+        }
+    }
+
+    /// Limit the number of connections that may be open at the same time.
+    ///
+    /// Once the limit is reached, no further connections are accepted until one
+    /// of the yielded streams is dropped. Pending connections stay in the
+    /// listener backlog in the meantime.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.limit = Some(Arc::new(Semaphore::new(max)));
+        self.acquire = None;
+        self.permit = None;
+        self
+    }
+
+    /// Stop accepting connections once `signal` completes.
+    ///
+    /// Connections that were already accepted are left untouched; use
+    /// [`VsockIncomingHandle::drain`] to wait for them to finish.
+    pub fn with_shutdown<F>(mut self, signal: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.signal = Some(Box::pin(signal));
+        self
+    }
+
+    /// Return a handle that can be used to shut this stream down and to wait for
+    /// its connections to drain.
+    pub fn handle(&self) -> VsockIncomingHandle {
+        VsockIncomingHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Return a reference to the underlying listener.
+    pub fn get_ref(&self) -> &VsockListener {
+        &self.listener
+    }
+
+    fn poll_permit(&mut self, cx: &mut Context<'_>) -> Poll<Option<OwnedSemaphorePermit>> {
+        let limit = match &self.limit {
+            Some(limit) => limit,
+            None => return Poll::Ready(None),
+        };
+        if self.permit.is_none() {
+            let acquire = self
+                .acquire
+                .get_or_insert_with(|| Box::pin(limit.clone().acquire_owned()));
+            let permit = ready!(acquire.as_mut().poll(cx)).expect("semaphore is never closed");
+            self.acquire = None;
+            self.permit = Some(permit);
+        }
+        Poll::Ready(self.permit.take())
+    }
+}
+
+impl Stream for VsockIncoming {
+    type Item = Result<VsockIncomingStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if let Some(signal) = this.signal.as_mut() {
+            if signal.as_mut().poll(cx).is_ready() {
+                this.signal = None;
+                this.shared.shutdown();
+            }
+        }
+
+        this.shared.waker.register(cx.waker());
+        if this.shared.shutdown.load(Ordering::SeqCst) {
+            return Poll::Ready(None);
+        }
+
+        let permit = ready!(this.poll_permit(cx));
+        let (stream, _) = match this.listener.poll_accept(cx) {
+            Poll::Ready(result) => result?,
+            Poll::Pending => {
+                // Hold on to the slot until a connection actually arrives.
+                this.permit = permit;
+                return Poll::Pending;
+            }
+        };
+
+        this.shared.active.send_modify(|active| *active += 1);
+        Poll::Ready(Some(Ok(VsockIncomingStream {
+            inner: stream,
+            _guard: ConnectionGuard {
+                shared: this.shared.clone(),
+                _permit: permit,
+            },
+        })))
+    }
+}
+
+impl fmt::Debug for VsockIncoming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VsockIncoming")
+            .field("listener", &self.listener)
+            .field("active", &*self.shared.active.borrow())
+            .finish()
+    }
+}
+
+/// A handle to a [`VsockIncoming`] that outlives the stream itself.
+#[derive(Clone)]
+pub struct VsockIncomingHandle {
+    shared: Arc<Shared>,
+}
+
+impl VsockIncomingHandle {
+    /// Stop accepting new connections. The incoming stream ends the next time
+    /// it is polled.
+    pub fn shutdown(&self) {
+        self.shared.shutdown();
+    }
+
+    /// Whether a shutdown was requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.shared.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// The number of accepted connections that are still open.
+    pub fn active_connections(&self) -> usize {
+        *self.shared.active.borrow()
+    }
+
+    /// Stop accepting new connections and wait up to `deadline` for the open
+    /// ones to be dropped.
+    ///
+    /// Returns `true` if all connections were closed before the deadline.
+    pub async fn drain(&self, deadline: Duration) -> bool {
+        self.shutdown();
+        let mut active = self.shared.active.subscribe();
+        let drained = tokio::time::timeout(deadline, active.wait_for(|active| *active == 0)).await;
+        drained.is_ok()
+    }
+}
+
+impl fmt::Debug for VsockIncomingHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VsockIncomingHandle")
+            .field("shutdown", &self.is_shutdown())
+            .field("active", &self.active_connections())
+            .finish()
+    }
+}
+
+/// Releases the connection slot of a [`VsockIncomingStream`] when dropped.
+struct ConnectionGuard {
+    shared: Arc<Shared>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.shared.active.send_modify(|active| *active -= 1);
+    }
+}
+
+/// A connection yielded by [`VsockIncoming`].
+///
+/// The connection counts towards the limits of the incoming stream until it is
+/// dropped.
+pub struct VsockIncomingStream {
+    inner: VsockStream,
+    _guard: ConnectionGuard,
+}
+
+impl VsockIncomingStream {
+    /// Return a reference to the underlying stream.
+    pub fn get_ref(&self) -> &VsockStream {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut VsockStream {
+        &mut self.inner
+    }
+}
+
+impl AsyncRead for VsockIncomingStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for VsockIncomingStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl fmt::Debug for VsockIncomingStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VsockIncomingStream")
+            .field(&self.inner)
+            .finish()
+    }
+}
@@ -16,16 +16,72 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+mod addr;
+#[cfg(feature = "async-io")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async-io")))]
+pub mod async_io;
 mod axum_support;
+mod channel;
+mod codec;
+mod either;
+mod filter;
+mod flags;
+#[cfg(feature = "futures-io")]
+mod futures_io_support;
+mod heartbeat;
+mod incoming;
 mod listener;
+mod multi;
+pub mod mux;
+mod policy;
+pub mod proxy;
+mod resolver;
+mod sendfile;
+mod serde_support;
+mod server;
 mod split;
 mod stream;
+mod sys;
+mod timeout;
 mod tonic_support;
+mod tower_support;
+#[cfg(feature = "io-uring")]
+#[cfg_attr(docsrs, doc(cfg(feature = "io-uring")))]
+pub mod uring;
+mod zerocopy;
 
+pub use addr::{ToVsockAddrs, VsockAddrParseError, VsockEndpoint};
+#[cfg(all(feature = "channel", feature = "bincode"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "channel", feature = "bincode"))))]
+pub use channel::BincodeEncoding;
+#[cfg(all(feature = "channel", feature = "ciborium"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "channel", feature = "ciborium"))))]
+pub use channel::CborEncoding;
+#[cfg(feature = "channel")]
+#[cfg_attr(docsrs, doc(cfg(feature = "channel")))]
+pub use channel::{JsonEncoding, RpcEncoding, RpcError, VsockRpcClient, VsockRpcServer};
+#[cfg(feature = "codec")]
+#[cfg_attr(docsrs, doc(cfg(feature = "codec")))]
+pub use codec::LengthDelimitedCodec;
+pub use either::{EitherAddr, EitherConnectInfo, EitherIncoming, EitherListener, EitherStream};
+pub use filter::AcceptFilter;
+pub use flags::{supports_flag_to_host, VsockFlags};
+pub use heartbeat::{HeartbeatHandle, HeartbeatStream, PeerDead};
+pub use incoming::{VsockIncoming, VsockIncomingHandle, VsockIncomingStream};
 pub use listener::{Incoming, VsockListener};
+pub use multi::{VsockMultiIncoming, VsockMultiListener};
+pub use policy::{PeerPolicy, PolicyAction, SharedPeerPolicy};
+pub use resolver::{
+    default_resolver, set_default_resolver, FileResolver, StaticResolver, VsockResolver,
+    DEFAULT_HOSTS_FILE,
+};
+pub use server::VsockServer;
 pub use split::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, WriteHalf};
 pub use stream::VsockStream;
-pub use tonic_support::VsockConnectInfo;
+pub use tonic_support::{VsockConnectInfo, VsockTransport};
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub use tower_support::{PeerPolicyLayer, PeerPolicyService};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use vsock::VMADDR_CID_LOCAL;
 pub use vsock::{VsockAddr, VMADDR_CID_ANY, VMADDR_CID_HOST, VMADDR_CID_HYPERVISOR};
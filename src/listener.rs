@@ -48,32 +48,50 @@ use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 
 use futures::{future::poll_fn, ready, stream::Stream};
-use std::mem;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::io::unix::AsyncFd;
 
+use crate::addr::{each_addr, ToVsockAddrs};
+use crate::filter::AcceptFilter;
+use crate::flags::VsockFlags;
 use crate::stream::VsockStream;
+use crate::sys;
 use crate::VsockAddr;
 
 /// An I/O object representing a Virtio socket listening for incoming connections.
 #[derive(Debug)]
 pub struct VsockListener {
     inner: AsyncFd<vsock::VsockListener>,
+    filter: Option<AcceptFilter>,
 }
 
 impl VsockListener {
     fn new(listener: vsock::VsockListener) -> Result<Self> {
         listener.set_nonblocking(true)?;
         Ok(Self {
-            inner: AsyncFd::new(listener)?,
+            // Safety: the vsock socket owns its fd, which stays open until the
+            // `AsyncFd` is dropped or consumed via `into_inner`.
+            inner: unsafe { AsyncFd::register(listener) }?,
+            filter: None,
         })
     }
 
     /// Create a new Virtio socket listener associated with this event loop.
-    pub fn bind(addr: VsockAddr) -> Result<Self> {
-        let l = vsock::VsockListener::bind_with_cid_port(addr.cid(), addr.port())?;
-        Self::new(l)
+    ///
+    /// If `addr` yields several addresses, the listener is bound to the first
+    /// one that succeeds.
+    pub fn bind<A: ToVsockAddrs>(addr: A) -> Result<Self> {
+        Self::bind_with_flags(addr, VsockFlags::empty())
+    }
+
+    /// Create a new Virtio socket listener, setting `flags` in the local
+    /// address.
+    ///
+    /// Kernels without support for the flags fail with
+    /// [`ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported).
+    pub fn bind_with_flags<A: ToVsockAddrs>(addr: A, flags: VsockFlags) -> Result<Self> {
+        each_addr(addr, |addr| Self::new(sys::bind(addr, flags)?))
     }
 
     /// Accepts a new incoming connection to this listener.
@@ -83,15 +101,30 @@ impl VsockListener {
 
     /// Attempt to accept a connection and create a new connected socket if
     /// successful.
+    ///
+    /// Connections rejected by the [`AcceptFilter`] of this listener are closed
+    /// right away and never returned.
     pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(VsockStream, VsockAddr)>> {
-        let (inner, addr) = ready!(self.poll_accept_std(cx))?;
-        let inner = VsockStream::new(inner)?;
-
-        Ok((inner, addr)).into()
+        loop {
+            let (inner, addr) = ready!(self.poll_accept_std(cx))?;
+            let slot = match &self.filter {
+                Some(filter) => match filter.admit(&addr) {
+                    Some(slot) => Some(slot),
+                    // Dropping the socket closes the connection.
+                    None => continue,
+                },
+                None => None,
+            };
+            let inner = VsockStream::new(inner)?.with_slot(slot);
+
+            return Ok((inner, addr)).into();
+        }
     }
 
     /// Attempt to accept a connection and create a new connected socket if
     /// successful.
+    ///
+    /// This bypasses the [`AcceptFilter`] of this listener.
     pub fn poll_accept_std(
         &self,
         cx: &mut Context<'_>,
@@ -114,6 +147,18 @@ impl VsockListener {
         self.inner.get_ref().local_addr()
     }
 
+    /// Restrict the connections this listener accepts.
+    ///
+    /// See [`AcceptFilter`] for details.
+    pub fn set_accept_filter(&mut self, filter: AcceptFilter) {
+        self.filter = Some(filter);
+    }
+
+    /// The accept filter of this listener, if any.
+    pub fn accept_filter(&self) -> Option<&AcceptFilter> {
+        self.filter.as_ref()
+    }
+
     /// Consumes this listener, returning a stream of the sockets this listener
     /// accepts.
     pub fn incoming(self) -> Incoming {
@@ -153,9 +198,7 @@ impl From<VsockListener> for OwnedFd {
 
 impl IntoRawFd for VsockListener {
     fn into_raw_fd(self) -> RawFd {
-        let fd = self.inner.get_ref().as_raw_fd();
-        mem::forget(self);
-        fd
+        OwnedFd::from(self).into_raw_fd()
     }
 }
 
@@ -0,0 +1,142 @@
+//! A listener bound to several Virtio socket ports at once.
+
+use std::io::{Error, ErrorKind, Result};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+use futures::{future::poll_fn, ready, stream::Stream};
+
+use crate::{AcceptFilter, VsockAddr, VsockListener, VsockStream};
+
+/// A listener accepting connections on several ports of the same CID.
+///
+/// Accepting polls the listeners in turn so that a busy port cannot starve
+/// the others. The port a connection arrived on is recorded in the local
+/// address of its [connect info](VsockStream::connect_info):
+///
+/// ```no_run
+/// # async fn serve() -> std::io::Result<()> {
+/// use tokio_vsock::{VsockMultiListener, VMADDR_CID_ANY};
+///
+/// let listener = VsockMultiListener::bind(VMADDR_CID_ANY, 8000..8010)?;
+/// loop {
+///     let (stream, _peer) = listener.accept().await?;
+///     match stream.connect_info().local_addr().map(|addr| addr.port()) {
+///         Some(8000) => { /* ... */ }
+///         _ => { /* ... */ }
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct VsockMultiListener {
+    listeners: Vec<VsockListener>,
+    next: AtomicUsize,
+}
+
+impl VsockMultiListener {
+    /// Bind a listener to each of `ports` on `cid`.
+    ///
+    /// Fails if any of the ports cannot be bound, or if `ports` is empty.
+    pub fn bind<P>(cid: u32, ports: P) -> Result<Self>
+    where
+        P: IntoIterator<Item = u32>,
+    {
+        let listeners = ports
+            .into_iter()
+            .map(|port| VsockListener::bind(VsockAddr::new(cid, port)))
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_listeners(listeners)
+    }
+
+    /// Accept connections from already bound `listeners`.
+    ///
+    /// Fails if `listeners` is empty.
+    pub fn from_listeners<I>(listeners: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = VsockListener>,
+    {
+        let listeners: Vec<_> = listeners.into_iter().collect();
+        if listeners.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "a multi-port listener needs at least one listener",
+            ));
+        }
+        Ok(Self {
+            listeners,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Also accept connections from `listener`.
+    pub fn push(&mut self, listener: VsockListener) {
+        self.listeners.push(listener);
+    }
+
+    /// Accepts a new incoming connection on any of the ports.
+    pub async fn accept(&self) -> Result<(VsockStream, VsockAddr)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// Attempt to accept a connection on any of the ports.
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(VsockStream, VsockAddr)>> {
+        let count = self.listeners.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        for i in 0..count {
+            let accepted = self.listeners[(start + i) % count].poll_accept(cx);
+            if accepted.is_ready() {
+                return accepted;
+            }
+        }
+
+        Poll::Pending
+    }
+
+    /// The local addresses of the listeners, in the order they were added.
+    pub fn local_addrs(&self) -> Result<Vec<VsockAddr>> {
+        self.listeners
+            .iter()
+            .map(VsockListener::local_addr)
+            .collect()
+    }
+
+    /// The listeners accepting connections.
+    pub fn listeners(&self) -> &[VsockListener] {
+        &self.listeners
+    }
+
+    /// Restrict the connections accepted on all ports.
+    ///
+    /// The listeners share the filter, so its connection limits apply across
+    /// ports. See [`AcceptFilter`] for details.
+    pub fn set_accept_filter(&mut self, filter: AcceptFilter) {
+        for listener in &mut self.listeners {
+            listener.set_accept_filter(filter.clone());
+        }
+    }
+
+    /// Consumes this listener, returning a stream of the sockets it accepts.
+    pub fn incoming(self) -> VsockMultiIncoming {
+        VsockMultiIncoming { inner: self }
+    }
+}
+
+/// Stream returned by [`VsockMultiListener::incoming`].
+///
+/// It can be passed to tonic's `serve_with_incoming`.
+#[derive(Debug)]
+pub struct VsockMultiIncoming {
+    inner: VsockMultiListener,
+}
+
+impl Stream for VsockMultiIncoming {
+    type Item = Result<VsockStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let (socket, _) = ready!(self.inner.poll_accept(cx))?;
+        Poll::Ready(Some(Ok(socket)))
+    }
+}
@@ -0,0 +1,806 @@
+//! Multiplexing of many logical streams over a single connection.
+//!
+//! Every Virtio socket connection costs an accept on the guest side and
+//! credit buffers in the kernel, and some VMMs restrict the ports that can be
+//! used. A [`MuxSession`] instead carries any number of [`MuxStream`]s over one
+//! [`VsockStream`](crate::VsockStream), using the framing of
+//! [yamux](https://github.com/hashicorp/yamux/blob/master/spec.md):
+//!
+//! - streams are opened, half-closed and reset independently,
+//! - each stream has its own flow control window, so a slow reader only
+//!   stalls its own stream,
+//! - pings keep the connection alive and detect dead peers.
+//!
+//! One end of the connection is the client, which opens odd stream ids, the
+//! other end the server, which opens even ones. Both ends can open and accept
+//! streams.
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use futures::StreamExt;
+//! use tokio::io::AsyncWriteExt;
+//! use tokio_vsock::mux::{MuxConfig, MuxSession};
+//! use tokio_vsock::{VsockStream, VMADDR_CID_HOST};
+//!
+//! let stream = VsockStream::connect((VMADDR_CID_HOST, 8000)).await?;
+//! let mut session = MuxSession::client(stream, MuxConfig::new());
+//!
+//! let mut logs = session.open_stream()?;
+//! logs.write_all(b"started\n").await?;
+//!
+//! while let Some(stream) = session.next().await {
+//!     // Streams opened by the peer.
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use futures::stream::Stream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+
+const VERSION: u8 = 0;
+
+const TYPE_DATA: u8 = 0;
+const TYPE_WINDOW_UPDATE: u8 = 1;
+const TYPE_PING: u8 = 2;
+const TYPE_GO_AWAY: u8 = 3;
+
+const FLAG_SYN: u16 = 0x1;
+const FLAG_ACK: u16 = 0x2;
+const FLAG_FIN: u16 = 0x4;
+const FLAG_RST: u16 = 0x8;
+
+const HEADER_LEN: usize = 12;
+
+/// The window both ends assume for a new stream.
+const INITIAL_WINDOW: u32 = 256 * 1024;
+/// Replies to the peer's frames that may wait to be written before no more
+/// frames are read, so that a peer that does not read cannot grow the queue.
+const MAX_QUEUED_REPLIES: usize = 64;
+
+/// Settings of a [`MuxSession`].
+#[derive(Debug, Clone)]
+pub struct MuxConfig {
+    window_size: u32,
+    max_frame_size: u32,
+    keepalive_interval: Option<Duration>,
+    max_streams: usize,
+}
+
+impl MuxConfig {
+    /// The default settings: a 256 KiB window, 16 KiB frames, a ping every 30
+    /// seconds and up to 1024 streams.
+    pub fn new() -> Self {
+        Self {
+            window_size: INITIAL_WINDOW,
+            max_frame_size: 16 * 1024,
+            keepalive_interval: Some(Duration::from_secs(30)),
+            max_streams: 1024,
+        }
+    }
+
+    /// The number of bytes the peer may send on a stream before it has to wait
+    /// for the stream to be read.
+    ///
+    /// Windows smaller than the 256 KiB yamux starts with are raised to it.
+    pub fn window_size(mut self, size: u32) -> Self {
+        self.window_size = size.max(INITIAL_WINDOW);
+        self
+    }
+
+    /// The largest data frame sent, which bounds how long a write on one
+    /// stream can hold up the others.
+    pub fn max_frame_size(mut self, size: u32) -> Self {
+        self.max_frame_size = size.max(1);
+        self
+    }
+
+    /// Ping the peer every `interval`, closing the session if a ping is not
+    /// answered before the next one is due. `None` disables pings.
+    pub fn keepalive_interval(mut self, interval: Option<Duration>) -> Self {
+        self.keepalive_interval = interval;
+        self
+    }
+
+    /// The number of streams that may be open at the same time. Streams opened
+    /// by the peer beyond this limit are reset.
+    pub fn max_streams(mut self, max: usize) -> Self {
+        self.max_streams = max;
+        self
+    }
+}
+
+impl Default for MuxConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    ty: u8,
+    flags: u16,
+    stream_id: u32,
+    length: u32,
+}
+
+impl Header {
+    fn new(ty: u8, flags: u16, stream_id: u32, length: u32) -> Self {
+        Self {
+            ty,
+            flags,
+            stream_id,
+            length,
+        }
+    }
+
+    fn encode(&self, frame: &mut Vec<u8>) {
+        frame.push(VERSION);
+        frame.push(self.ty);
+        frame.extend_from_slice(&self.flags.to_be_bytes());
+        frame.extend_from_slice(&self.stream_id.to_be_bytes());
+        frame.extend_from_slice(&self.length.to_be_bytes());
+    }
+
+    fn decode(raw: &[u8; HEADER_LEN]) -> Result<Self> {
+        if raw[0] != VERSION {
+            return Err(protocol_error("unsupported mux version"));
+        }
+        if raw[1] > TYPE_GO_AWAY {
+            return Err(protocol_error("unknown mux frame type"));
+        }
+        Ok(Self {
+            ty: raw[1],
+            flags: u16::from_be_bytes([raw[2], raw[3]]),
+            stream_id: u32::from_be_bytes([raw[4], raw[5], raw[6], raw[7]]),
+            length: u32::from_be_bytes([raw[8], raw[9], raw[10], raw[11]]),
+        })
+    }
+
+    fn has(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+fn protocol_error(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn session_closed(kind: ErrorKind) -> Error {
+    Error::new(kind, "mux session closed")
+}
+
+#[derive(Debug)]
+struct StreamState {
+    recv: VecDeque<u8>,
+    /// The number of bytes the peer may still send.
+    recv_window: u32,
+    /// Bytes read since the last window update.
+    consumed: u32,
+    send_window: u32,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+    local_closed: bool,
+    remote_closed: bool,
+    reset: bool,
+}
+
+impl StreamState {
+    fn new(recv_window: u32) -> Self {
+        Self {
+            recv: VecDeque::new(),
+            recv_window,
+            consumed: 0,
+            send_window: INITIAL_WINDOW,
+            read_waker: None,
+            write_waker: None,
+            local_closed: false,
+            remote_closed: false,
+            reset: false,
+        }
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    streams: HashMap<u32, StreamState>,
+    outgoing: VecDeque<Vec<u8>>,
+    /// Frames in `outgoing` queued in reply to frames of the peer.
+    queued_replies: usize,
+    inbound: VecDeque<u32>,
+    accept_waker: Option<Waker>,
+    next_id: u32,
+    /// The highest stream id the peer opened so far.
+    last_remote_id: u32,
+    /// Why the session ended, if it did.
+    closed: Option<ErrorKind>,
+    /// The peer does not accept new streams any more.
+    remote_go_away: bool,
+    next_ping: u32,
+    pending_ping: Option<u32>,
+}
+
+impl Inner {
+    fn queue(&mut self, header: Header, body: &[u8]) {
+        let mut frame = Vec::with_capacity(HEADER_LEN + body.len());
+        header.encode(&mut frame);
+        frame.extend_from_slice(body);
+        self.outgoing.push_back(frame);
+    }
+
+    fn reply(&mut self, header: Header) {
+        self.queue(header, &[]);
+        self.queued_replies += 1;
+    }
+
+    fn close(&mut self, kind: ErrorKind) {
+        if self.closed.is_some() {
+            return;
+        }
+        self.closed = Some(kind);
+        for state in self.streams.values_mut() {
+            state.wake();
+        }
+        if let Some(waker) = self.accept_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    inner: Mutex<Inner>,
+    /// Wakes the task writing frames.
+    writer: Notify,
+    /// Wakes the task reading frames once replies were written.
+    reader: Notify,
+    config: MuxConfig,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn close(&self, kind: ErrorKind) {
+        self.lock().close(kind);
+        self.writer.notify_one();
+    }
+
+    fn handle(&self, header: Header, body: &[u8]) {
+        let mut inner = self.lock();
+        match header.ty {
+            TYPE_DATA | TYPE_WINDOW_UPDATE => self.handle_stream_frame(&mut inner, header, body),
+            TYPE_PING => {
+                if header.has(FLAG_SYN) {
+                    inner.reply(Header::new(TYPE_PING, FLAG_ACK, 0, header.length));
+                } else if header.has(FLAG_ACK) && inner.pending_ping == Some(header.length) {
+                    inner.pending_ping = None;
+                }
+            }
+            _ => inner.remote_go_away = true,
+        }
+        drop(inner);
+        self.writer.notify_one();
+    }
+
+    fn handle_stream_frame(&self, inner: &mut Inner, header: Header, body: &[u8]) {
+        let id = header.stream_id;
+        if header.has(FLAG_SYN) {
+            // The peer opens streams with the other parity than ours, in
+            // increasing order, so they can never collide with our own.
+            let valid = id != 0 && id % 2 != inner.next_id % 2 && id > inner.last_remote_id;
+            if !valid {
+                inner.reply(Header::new(TYPE_WINDOW_UPDATE, FLAG_RST, id, 0));
+                return;
+            }
+            inner.last_remote_id = id;
+            let reject = inner.streams.contains_key(&id)
+                || inner.streams.len() >= self.config.max_streams
+                || inner.closed.is_some();
+            if reject {
+                inner.reply(Header::new(TYPE_WINDOW_UPDATE, FLAG_RST, id, 0));
+                return;
+            }
+            inner
+                .streams
+                .insert(id, StreamState::new(self.config.window_size));
+            inner.inbound.push_back(id);
+            if let Some(waker) = inner.accept_waker.take() {
+                waker.wake();
+            }
+            let delta = self.config.window_size - INITIAL_WINDOW;
+            inner.reply(Header::new(TYPE_WINDOW_UPDATE, FLAG_ACK, id, delta));
+        }
+
+        // Frames for streams dropped locally are discarded.
+        let state = match inner.streams.get_mut(&id) {
+            Some(state) => state,
+            None => return,
+        };
+        if header.ty == TYPE_WINDOW_UPDATE {
+            state.send_window = state.send_window.saturating_add(header.length);
+            if let Some(waker) = state.write_waker.take() {
+                waker.wake();
+            }
+        } else if !body.is_empty() {
+            if body.len() as u32 > state.recv_window || state.remote_closed {
+                // The peer ignored flow control or wrote after closing.
+                state.reset = true;
+                state.wake();
+                inner.reply(Header::new(TYPE_WINDOW_UPDATE, FLAG_RST, id, 0));
+                return;
+            }
+            state.recv_window -= body.len() as u32;
+            state.recv.extend(body);
+        }
+        if header.has(FLAG_FIN) {
+            state.remote_closed = true;
+        }
+        if header.has(FLAG_RST) {
+            state.reset = true;
+        }
+        if !body.is_empty() || header.has(FLAG_FIN) || header.has(FLAG_RST) {
+            state.wake();
+        }
+    }
+}
+
+/// A connection carrying many [`MuxStream`]s.
+///
+/// The session runs on two tasks spawned on the current Tokio runtime, one
+/// reading and one writing frames. It yields the streams opened by the peer
+/// as a [`Stream`], which ends once the session is closed.
+///
+/// Dropping the session closes it, which fails all of its streams.
+pub struct MuxSession {
+    shared: Arc<Shared>,
+}
+
+impl MuxSession {
+    /// Start a session on `io` as the client, which opens odd stream ids.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn client<T>(io: T, config: MuxConfig) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        Self::new(io, config, 1)
+    }
+
+    /// Start a session on `io` as the server, which opens even stream ids.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn server<T>(io: T, config: MuxConfig) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        Self::new(io, config, 2)
+    }
+
+    fn new<T>(io: T, config: MuxConfig, first_id: u32) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            inner: Mutex::new(Inner {
+                streams: HashMap::new(),
+                outgoing: VecDeque::new(),
+                queued_replies: 0,
+                inbound: VecDeque::new(),
+                accept_waker: None,
+                next_id: first_id,
+                last_remote_id: 0,
+                closed: None,
+                remote_go_away: false,
+                next_ping: 0,
+                pending_ping: None,
+            }),
+            writer: Notify::new(),
+            reader: Notify::new(),
+            config,
+        });
+        let (rd, wr) = tokio::io::split(io);
+        let reader = tokio::spawn(read_frames(shared.clone(), rd)).abort_handle();
+        tokio::spawn(write_frames(shared.clone(), wr, reader));
+        Self { shared }
+    }
+
+    /// Open a new stream to the peer.
+    ///
+    /// The peer learns about the stream right away, without waiting for data to
+    /// be written.
+    pub fn open_stream(&self) -> Result<MuxStream> {
+        let mut inner = self.shared.lock();
+        if let Some(kind) = inner.closed {
+            return Err(session_closed(kind));
+        }
+        if inner.remote_go_away {
+            return Err(Error::new(
+                ErrorKind::ConnectionRefused,
+                "the peer does not accept new mux streams",
+            ));
+        }
+        if inner.streams.len() >= self.shared.config.max_streams {
+            return Err(Error::other("too many open mux streams"));
+        }
+        let id = inner.next_id;
+        inner.next_id = id
+            .checked_add(2)
+            .ok_or_else(|| Error::other("mux stream ids exhausted"))?;
+
+        let window = self.shared.config.window_size;
+        inner.streams.insert(id, StreamState::new(window));
+        inner.queue(
+            Header::new(TYPE_WINDOW_UPDATE, FLAG_SYN, id, window - INITIAL_WINDOW),
+            &[],
+        );
+        drop(inner);
+        self.shared.writer.notify_one();
+
+        Ok(MuxStream {
+            id,
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// Attempt to accept a stream opened by the peer.
+    ///
+    /// Returns `None` once the session is closed.
+    pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Option<MuxStream>> {
+        let mut inner = self.shared.lock();
+        if let Some(id) = inner.inbound.pop_front() {
+            return Poll::Ready(Some(MuxStream {
+                id,
+                shared: self.shared.clone(),
+            }));
+        }
+        if inner.closed.is_some() {
+            return Poll::Ready(None);
+        }
+        inner.accept_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Accept a stream opened by the peer.
+    ///
+    /// Returns `None` once the session is closed.
+    pub async fn accept(&self) -> Option<MuxStream> {
+        future::poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// Tell the peer that the session is going away and close it.
+    pub fn close(&self) {
+        let mut inner = self.shared.lock();
+        if inner.closed.is_none() {
+            inner.queue(Header::new(TYPE_GO_AWAY, 0, 0, 0), &[]);
+            inner.close(ErrorKind::ConnectionAborted);
+        }
+        drop(inner);
+        self.shared.writer.notify_one();
+    }
+
+    /// Whether the session is closed.
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed.is_some()
+    }
+
+    /// The number of open streams.
+    pub fn num_streams(&self) -> usize {
+        self.shared.lock().streams.len()
+    }
+}
+
+impl Stream for MuxSession {
+    type Item = MuxStream;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_accept(cx)
+    }
+}
+
+impl Drop for MuxSession {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl fmt::Debug for MuxSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.shared.lock();
+        f.debug_struct("MuxSession")
+            .field("streams", &inner.streams.len())
+            .field("closed", &inner.closed)
+            .finish()
+    }
+}
+
+async fn read_frames<R: AsyncRead + Unpin>(shared: Arc<Shared>, mut rd: R) {
+    let mut raw = [0u8; HEADER_LEN];
+    let mut body = Vec::new();
+    let err = loop {
+        while shared.lock().queued_replies >= MAX_QUEUED_REPLIES {
+            shared.reader.notified().await;
+        }
+        if let Err(err) = rd.read_exact(&mut raw).await {
+            break err;
+        }
+        let header = match Header::decode(&raw) {
+            Ok(header) => header,
+            Err(err) => break err,
+        };
+        body.clear();
+        if header.ty == TYPE_DATA && header.length > 0 {
+            if header.length > shared.config.window_size {
+                break protocol_error("mux frame larger than the window");
+            }
+            body.resize(header.length as usize, 0);
+            if let Err(err) = rd.read_exact(&mut body).await {
+                break err;
+            }
+        }
+        shared.handle(header, &body);
+    };
+    let kind = match err.kind() {
+        ErrorKind::UnexpectedEof => ErrorKind::ConnectionAborted,
+        kind => kind,
+    };
+    shared.close(kind);
+}
+
+async fn write_frames<W: AsyncWrite + Unpin>(shared: Arc<Shared>, mut wr: W, reader: AbortHandle) {
+    // The reader may be stuck on a dead peer once the session is closed.
+    let _reader = AbortOnDrop(reader);
+    let mut keepalive = shared
+        .config
+        .keepalive_interval
+        .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+    loop {
+        let (frames, replies, closed) = {
+            let mut inner = shared.lock();
+            (
+                std::mem::take(&mut inner.outgoing),
+                inner.queued_replies,
+                inner.closed.is_some(),
+            )
+        };
+        for frame in &frames {
+            if wr.write_all(frame).await.is_err() {
+                shared.close(ErrorKind::BrokenPipe);
+                return;
+            }
+        }
+        if !frames.is_empty() && wr.flush().await.is_err() {
+            shared.close(ErrorKind::BrokenPipe);
+            return;
+        }
+        if replies > 0 {
+            shared.lock().queued_replies -= replies;
+            shared.reader.notify_one();
+        }
+        if closed {
+            let _ = wr.shutdown().await;
+            return;
+        }
+
+        let notified = Box::pin(shared.writer.notified());
+        let interval = match &mut keepalive {
+            Some(interval) => interval,
+            None => {
+                notified.await;
+                continue;
+            }
+        };
+        if let Either::Right(_) = future::select(notified, Box::pin(interval.tick())).await {
+            let mut inner = shared.lock();
+            if inner.pending_ping.is_some() {
+                inner.close(ErrorKind::TimedOut);
+            } else {
+                let id = inner.next_ping;
+                inner.next_ping = id.wrapping_add(1);
+                inner.pending_ping = Some(id);
+                inner.queue(Header::new(TYPE_PING, FLAG_SYN, 0, id), &[]);
+            }
+        }
+    }
+}
+
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// A logical stream of a [`MuxSession`].
+///
+/// Writes are handed to the session and sent in frames of at most
+/// [`max_frame_size`](MuxConfig::max_frame_size) bytes; once the peer's window
+/// is used up, writes wait for the peer to read. Shutting the stream down sends
+/// the end of the stream to the peer, and dropping it does the same if it was
+/// not shut down yet. Use [`reset`](MuxStream::reset) to abort a stream
+/// instead.
+pub struct MuxStream {
+    id: u32,
+    shared: Arc<Shared>,
+}
+
+impl MuxStream {
+    /// The id of this stream within its session.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Abort the stream. Reads and writes fail on both ends afterwards.
+    pub fn reset(&mut self) {
+        let mut inner = self.shared.lock();
+        if let Some(state) = inner.streams.get_mut(&self.id) {
+            if !state.reset {
+                state.reset = true;
+                state.wake();
+                inner.queue(Header::new(TYPE_WINDOW_UPDATE, FLAG_RST, self.id, 0), &[]);
+            }
+        }
+        drop(inner);
+        self.shared.writer.notify_one();
+    }
+}
+
+impl AsyncRead for MuxStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let mut guard = self.shared.lock();
+        let inner = &mut *guard;
+        let closed = inner.closed;
+        let state = match inner.streams.get_mut(&self.id) {
+            Some(state) => state,
+            None => return Poll::Ready(Err(session_closed(ErrorKind::NotConnected))),
+        };
+
+        if !state.recv.is_empty() {
+            let n = buf.remaining().min(state.recv.len());
+            let (front, back) = state.recv.as_slices();
+            let from_front = n.min(front.len());
+            buf.put_slice(&front[..from_front]);
+            buf.put_slice(&back[..n - from_front]);
+            state.recv.drain(..n);
+
+            // Give the peer more credit once half of the window was read.
+            state.consumed += n as u32;
+            if state.consumed >= self.shared.config.window_size / 2 && !state.remote_closed {
+                let delta = std::mem::take(&mut state.consumed);
+                state.recv_window += delta;
+                inner.queue(Header::new(TYPE_WINDOW_UPDATE, 0, self.id, delta), &[]);
+                drop(guard);
+                self.shared.writer.notify_one();
+            }
+            return Poll::Ready(Ok(()));
+        }
+        if state.reset {
+            return Poll::Ready(Err(Error::new(
+                ErrorKind::ConnectionReset,
+                "mux stream reset",
+            )));
+        }
+        if state.remote_closed {
+            return Poll::Ready(Ok(()));
+        }
+        if let Some(kind) = closed {
+            return Poll::Ready(Err(session_closed(kind)));
+        }
+        state.read_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for MuxStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let mut guard = self.shared.lock();
+        let inner = &mut *guard;
+        let closed = inner.closed;
+        let state = match inner.streams.get_mut(&self.id) {
+            Some(state) => state,
+            None => return Poll::Ready(Err(session_closed(ErrorKind::NotConnected))),
+        };
+
+        if state.reset {
+            return Poll::Ready(Err(Error::new(
+                ErrorKind::ConnectionReset,
+                "mux stream reset",
+            )));
+        }
+        if state.local_closed {
+            return Poll::Ready(Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "mux stream was shut down",
+            )));
+        }
+        if let Some(kind) = closed {
+            return Poll::Ready(Err(session_closed(kind)));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        if state.send_window == 0 {
+            state.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = buf
+            .len()
+            .min(state.send_window as usize)
+            .min(self.shared.config.max_frame_size as usize);
+        state.send_window -= n as u32;
+        inner.queue(Header::new(TYPE_DATA, 0, self.id, n as u32), &buf[..n]);
+        drop(guard);
+        self.shared.writer.notify_one();
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+        // Frames are handed to the session as soon as they are written.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut inner = self.shared.lock();
+        let closed = inner.closed.is_some();
+        if let Some(state) = inner.streams.get_mut(&self.id) {
+            if !state.local_closed && !state.reset && !closed {
+                state.local_closed = true;
+                inner.queue(Header::new(TYPE_DATA, FLAG_FIN, self.id, 0), &[]);
+            }
+        }
+        drop(inner);
+        self.shared.writer.notify_one();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        let mut inner = self.shared.lock();
+        let closed = inner.closed.is_some();
+        if let Some(state) = inner.streams.remove(&self.id) {
+            if !state.local_closed && !state.reset && !closed {
+                inner.queue(Header::new(TYPE_DATA, FLAG_FIN, self.id, 0), &[]);
+            }
+        }
+        drop(inner);
+        self.shared.writer.notify_one();
+    }
+}
+
+impl fmt::Debug for MuxStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MuxStream").field("id", &self.id).finish()
+    }
+}
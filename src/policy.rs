@@ -0,0 +1,170 @@
+//! Allow/deny policies for Virtio socket peers.
+
+use std::ops::{Bound, RangeBounds, RangeInclusive};
+use std::sync::{Arc, RwLock};
+
+use crate::VsockAddr;
+
+/// Whether a rule of a [`PeerPolicy`] allows or denies the peers it matches.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PolicyAction {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct PeerRule {
+    action: PolicyAction,
+    cids: RangeInclusive<u32>,
+    ports: RangeInclusive<u32>,
+}
+
+/// An ordered list of rules deciding which peers may talk to a service.
+///
+/// Rules are matched against the CID and port of the peer address in the order
+/// they were added; the first matching rule wins. Peers not matched by any rule
+/// get the default action.
+///
+/// ```
+/// use tokio_vsock::{PeerPolicy, VsockAddr};
+///
+/// // Allow guests 3 to 9, except guest 5.
+/// let policy = PeerPolicy::deny_all().deny_cid(5).allow(3..10, ..);
+/// assert!(policy.is_allowed(&VsockAddr::new(3, 1024)));
+/// assert!(!policy.is_allowed(&VsockAddr::new(5, 1024)));
+/// assert!(!policy.is_allowed(&VsockAddr::new(10, 1024)));
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PeerPolicy {
+    rules: Vec<PeerRule>,
+    default: PolicyAction,
+}
+
+impl PeerPolicy {
+    /// A policy allowing every peer not denied by a rule.
+    pub fn allow_all() -> Self {
+        Self {
+            rules: Vec::new(),
+            default: PolicyAction::Allow,
+        }
+    }
+
+    /// A policy denying every peer not allowed by a rule.
+    pub fn deny_all() -> Self {
+        Self {
+            rules: Vec::new(),
+            default: PolicyAction::Deny,
+        }
+    }
+
+    /// Add a rule allowing peers with a CID in `cids` and a port in `ports`.
+    pub fn allow(self, cids: impl RangeBounds<u32>, ports: impl RangeBounds<u32>) -> Self {
+        self.rule(PolicyAction::Allow, cids, ports)
+    }
+
+    /// Add a rule denying peers with a CID in `cids` and a port in `ports`.
+    pub fn deny(self, cids: impl RangeBounds<u32>, ports: impl RangeBounds<u32>) -> Self {
+        self.rule(PolicyAction::Deny, cids, ports)
+    }
+
+    /// Add a rule allowing any port of the peer `cid`.
+    pub fn allow_cid(self, cid: u32) -> Self {
+        self.allow(cid..=cid, ..)
+    }
+
+    /// Add a rule denying any port of the peer `cid`.
+    pub fn deny_cid(self, cid: u32) -> Self {
+        self.deny(cid..=cid, ..)
+    }
+
+    /// Add a rule matching peers with a CID in `cids` and a port in `ports`.
+    pub fn rule(
+        mut self,
+        action: PolicyAction,
+        cids: impl RangeBounds<u32>,
+        ports: impl RangeBounds<u32>,
+    ) -> Self {
+        self.rules.push(PeerRule {
+            action,
+            cids: to_inclusive(cids),
+            ports: to_inclusive(ports),
+        });
+        self
+    }
+
+    /// The action taken for `addr`.
+    pub fn action(&self, addr: &VsockAddr) -> PolicyAction {
+        self.rules
+            .iter()
+            .find(|rule| rule.cids.contains(&addr.cid()) && rule.ports.contains(&addr.port()))
+            .map_or(self.default, |rule| rule.action)
+    }
+
+    /// Whether `addr` is allowed by this policy.
+    pub fn is_allowed(&self, addr: &VsockAddr) -> bool {
+        self.action(addr) == PolicyAction::Allow
+    }
+}
+
+impl Default for PeerPolicy {
+    fn default() -> Self {
+        Self::allow_all()
+    }
+}
+
+fn to_inclusive(range: impl RangeBounds<u32>) -> RangeInclusive<u32> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        // Nothing lies above `u32::MAX`.
+        Bound::Excluded(&u32::MAX) => return RangeInclusive::new(1, 0),
+        Bound::Excluded(&start) => start + 1,
+        Bound::Unbounded => 0,
+    };
+    // An empty range such as `3..3` yields `3..=2`, which matches nothing.
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end,
+        Bound::Excluded(&0) => return RangeInclusive::new(1, 0),
+        Bound::Excluded(&end) => end - 1,
+        Bound::Unbounded => u32::MAX,
+    };
+    start..=end
+}
+
+/// A [`PeerPolicy`] that can be replaced at runtime.
+///
+/// Clones share the same policy, so a policy reloaded through one clone is seen
+/// by every service or listener holding another.
+#[derive(Debug, Clone, Default)]
+pub struct SharedPeerPolicy {
+    inner: Arc<RwLock<Arc<PeerPolicy>>>,
+}
+
+impl SharedPeerPolicy {
+    /// Wrap `policy` so that it can be shared and reloaded.
+    pub fn new(policy: PeerPolicy) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Arc::new(policy))),
+        }
+    }
+
+    /// Replace the current policy.
+    pub fn reload(&self, policy: PeerPolicy) {
+        *self.inner.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(policy);
+    }
+
+    /// The policy currently in effect.
+    pub fn current(&self) -> Arc<PeerPolicy> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Whether `addr` is allowed by the policy currently in effect.
+    pub fn is_allowed(&self, addr: &VsockAddr) -> bool {
+        self.current().is_allowed(addr)
+    }
+}
+
+impl From<PeerPolicy> for SharedPeerPolicy {
+    fn from(policy: PeerPolicy) -> Self {
+        Self::new(policy)
+    }
+}
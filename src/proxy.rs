@@ -0,0 +1,284 @@
+//! Forwarding between Virtio sockets and other sockets without copying the
+//! data through user space.
+//!
+//! [`splice_bidirectional`] moves data through a kernel pipe for each
+//! direction with `splice`, instead of reading it into a buffer and writing
+//! it out again like [`tokio::io::copy_bidirectional`], which it falls back
+//! to where the kernel cannot splice from a socket.
+
+use std::io::{Error, ErrorKind, Result};
+use std::os::fd::OwnedFd;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::task::{Context, Poll};
+
+use futures::future::poll_fn;
+use futures::ready;
+use libc::*;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, Interest};
+use tokio::net::{TcpStream, UnixStream};
+
+use crate::sys::{self, PIPE_SIZE};
+use crate::VsockStream;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A socket that [`splice_bidirectional`] can forward from and to.
+///
+/// This is implemented for [`VsockStream`], and Tokio's [`TcpStream`] and
+/// [`UnixStream`].
+pub trait SpliceSocket: AsRawFd + AsyncRead + AsyncWrite + Unpin + sealed::Sealed {
+    /// Wait for `interest` and run `op`, until it does not fail with
+    /// [`ErrorKind::WouldBlock`].
+    #[doc(hidden)]
+    fn poll_op(
+        &self,
+        cx: &mut Context<'_>,
+        interest: Interest,
+        op: &mut dyn FnMut() -> Result<usize>,
+    ) -> Poll<Result<usize>>;
+
+    /// Shut down the writing half, once the data written so far was sent.
+    #[doc(hidden)]
+    fn poll_shutdown_write(&self, cx: &mut Context<'_>) -> Poll<Result<()>>;
+}
+
+impl sealed::Sealed for VsockStream {}
+
+impl SpliceSocket for VsockStream {
+    fn poll_op(
+        &self,
+        cx: &mut Context<'_>,
+        interest: Interest,
+        op: &mut dyn FnMut() -> Result<usize>,
+    ) -> Poll<Result<usize>> {
+        let result = ready!(self.poll_io(cx, interest, |_| op()));
+        if let Ok(1..) = result {
+            self.touch();
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_shutdown_write(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_shutdown_priv(cx)
+    }
+}
+
+macro_rules! impl_splice_socket {
+    ($ty:ty) => {
+        impl sealed::Sealed for $ty {}
+
+        impl SpliceSocket for $ty {
+            fn poll_op(
+                &self,
+                cx: &mut Context<'_>,
+                interest: Interest,
+                op: &mut dyn FnMut() -> Result<usize>,
+            ) -> Poll<Result<usize>> {
+                loop {
+                    if interest.is_readable() {
+                        ready!(self.poll_read_ready(cx))?;
+                    } else {
+                        ready!(self.poll_write_ready(cx))?;
+                    }
+                    match self.try_io(interest, &mut *op) {
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => continue,
+                        result => return Poll::Ready(result),
+                    }
+                }
+            }
+
+            fn poll_shutdown_write(&self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+                // Tokio's streams hold no written data back, so there is
+                // nothing to wait for.
+                let ret = unsafe { shutdown(self.as_raw_fd(), SHUT_WR) };
+                if ret < 0 {
+                    Poll::Ready(Err(Error::last_os_error()))
+                } else {
+                    Poll::Ready(Ok(()))
+                }
+            }
+        }
+    };
+}
+
+impl_splice_socket!(TcpStream);
+impl_splice_socket!(UnixStream);
+
+fn splice_fds(from: RawFd, to: RawFd, len: usize) -> Result<usize> {
+    let ret = unsafe {
+        splice(
+            from,
+            ptr::null_mut(),
+            to,
+            ptr::null_mut(),
+            len,
+            SPLICE_F_MOVE | SPLICE_F_NONBLOCK,
+        )
+    };
+    if ret < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// One direction of forwarding, through its own pipe.
+struct Transfer {
+    pipe_rd: OwnedFd,
+    pipe_wr: OwnedFd,
+    /// The number of bytes in the pipe.
+    buffered: usize,
+    read_done: bool,
+    done: bool,
+    amount: u64,
+}
+
+impl Transfer {
+    fn new() -> Result<Self> {
+        let (pipe_rd, pipe_wr) = sys::pipe(O_NONBLOCK)?;
+        Ok(Self {
+            pipe_rd,
+            pipe_wr,
+            buffered: 0,
+            read_done: false,
+            done: false,
+            amount: 0,
+        })
+    }
+
+    /// Write out what is left in the pipe, to continue without splicing.
+    async fn write_buffered<W>(&mut self, writer: &mut W) -> Result<()>
+    where
+        W: SpliceSocket + ?Sized,
+    {
+        let mut buf = vec![0u8; self.buffered];
+        while self.buffered > 0 {
+            let ret = unsafe {
+                read(
+                    self.pipe_rd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut c_void,
+                    self.buffered,
+                )
+            };
+            if ret < 0 {
+                let err = Error::last_os_error();
+                if err.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            let n = ret as usize;
+            writer.write_all(&buf[..n]).await?;
+            self.buffered -= n;
+            self.amount += n as u64;
+        }
+        Ok(())
+    }
+
+    fn poll_transfer<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        reader: &R,
+        writer: &W,
+    ) -> Poll<Result<u64>>
+    where
+        R: SpliceSocket + ?Sized,
+        W: SpliceSocket + ?Sized,
+    {
+        loop {
+            if self.done {
+                return Poll::Ready(Ok(self.amount));
+            }
+            if self.buffered > 0 {
+                let (pipe, to, len) = (self.pipe_rd.as_raw_fd(), writer.as_raw_fd(), self.buffered);
+                let mut op = || splice_fds(pipe, to, len);
+                match ready!(writer.poll_op(cx, Interest::WRITABLE, &mut op)) {
+                    Ok(0) => return Poll::Ready(Err(ErrorKind::WriteZero.into())),
+                    Ok(n) => {
+                        self.buffered -= n;
+                        self.amount += n as u64;
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            } else if self.read_done {
+                // Pass the end of the stream on to the writer.
+                match ready!(writer.poll_shutdown_write(cx)) {
+                    Err(err) if err.kind() != ErrorKind::NotConnected => {
+                        return Poll::Ready(Err(err))
+                    }
+                    _ => self.done = true,
+                }
+            } else {
+                let (from, pipe) = (reader.as_raw_fd(), self.pipe_wr.as_raw_fd());
+                let mut op = || splice_fds(from, pipe, PIPE_SIZE);
+                match ready!(reader.poll_op(cx, Interest::READABLE, &mut op)) {
+                    Ok(0) => self.read_done = true,
+                    Ok(n) => self.buffered = n,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            }
+        }
+    }
+}
+
+/// Forward data between `vsock` and `other` in both directions until both
+/// reached the end of their stream, moving it through kernel pipes with
+/// `splice`.
+///
+/// When one side shuts down its writing half, the other side's writing half
+/// is shut down once all data was forwarded, while the opposite direction
+/// keeps going. Returns the number of bytes forwarded from `vsock` to `other`
+/// and from `other` to `vsock`, like [`tokio::io::copy_bidirectional`].
+///
+/// On kernels that cannot splice from one of the sockets, the data already
+/// in the pipes is written out and forwarding continues with
+/// [`tokio::io::copy_bidirectional`].
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use tokio::net::TcpStream;
+/// use tokio_vsock::{proxy, VsockListener, VMADDR_CID_ANY};
+///
+/// let listener = VsockListener::bind((VMADDR_CID_ANY, 8000))?;
+/// loop {
+///     let (mut vsock, _) = listener.accept().await?;
+///     tokio::spawn(async move {
+///         let mut tcp = TcpStream::connect("127.0.0.1:8080").await?;
+///         let (to_tcp, to_vsock) = proxy::splice_bidirectional(&mut vsock, &mut tcp).await?;
+///         println!("forwarded {} and {} bytes", to_tcp, to_vsock);
+///         Ok::<_, std::io::Error>(())
+///     });
+/// }
+/// # }
+/// ```
+pub async fn splice_bidirectional<A, B>(vsock: &mut A, other: &mut B) -> Result<(u64, u64)>
+where
+    A: SpliceSocket + ?Sized,
+    B: SpliceSocket + ?Sized,
+{
+    let mut a_to_b = Transfer::new()?;
+    let mut b_to_a = Transfer::new()?;
+    let spliced: Result<_> = poll_fn(|cx| {
+        let a_to_b = a_to_b.poll_transfer(cx, &*vsock, &*other)?;
+        let b_to_a = b_to_a.poll_transfer(cx, &*other, &*vsock)?;
+        match (a_to_b, b_to_a) {
+            (Poll::Ready(a_to_b), Poll::Ready(b_to_a)) => Poll::Ready(Ok((a_to_b, b_to_a))),
+            _ => Poll::Pending,
+        }
+    })
+    .await;
+    match spliced {
+        Err(err) if err.raw_os_error() == Some(EINVAL) => {}
+        result => return result,
+    }
+
+    a_to_b.write_buffered(other).await?;
+    b_to_a.write_buffered(vsock).await?;
+    let (copied_a_to_b, copied_b_to_a) = tokio::io::copy_bidirectional(vsock, other).await?;
+    Ok((a_to_b.amount + copied_a_to_b, b_to_a.amount + copied_b_to_a))
+}
@@ -0,0 +1,196 @@
+//! Resolution of guest names to CIDs.
+//!
+//! Addresses such as `vsock://builder:8000` name the peer instead of giving its
+//! CID. The name is looked up with the default [`VsockResolver`], which reads
+//! `/etc/vsock-hosts` unless replaced with [`set_default_resolver`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::addr::{parse_cid, split_addr};
+use crate::VsockAddr;
+
+/// The file read by the default resolver.
+pub const DEFAULT_HOSTS_FILE: &str = "/etc/vsock-hosts";
+
+static DEFAULT_RESOLVER: RwLock<Option<Arc<dyn VsockResolver>>> = RwLock::new(None);
+
+/// Looks up the CID of a named peer.
+///
+/// Converting an address with [`ToVsockAddrs`](crate::ToVsockAddrs) calls the
+/// resolver directly, for instance when binding a listener. Async connects
+/// call it on a blocking thread instead, so lookups may read files.
+pub trait VsockResolver: fmt::Debug + Send + Sync {
+    /// Return the CID of `name`, or `None` if the name is unknown.
+    fn resolve(&self, name: &str) -> io::Result<Option<u32>>;
+
+    /// Resolve an address string such as `vsock://builder:8000`, where the
+    /// host part may be a CID, a symbolic CID or a name known to this resolver.
+    fn resolve_addr(&self, addr: &str) -> io::Result<VsockAddr> {
+        let (host, port) = split_addr(addr)?;
+        let cid = match parse_cid(host) {
+            Some(cid) => cid,
+            None => self.resolve(host)?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("unknown vsock host `{}`", host),
+                )
+            })?,
+        };
+        Ok(VsockAddr::new(cid, port))
+    }
+}
+
+/// Replace the resolver used for names in address strings.
+pub fn set_default_resolver(resolver: Arc<dyn VsockResolver>) {
+    *DEFAULT_RESOLVER.write().unwrap_or_else(|e| e.into_inner()) = Some(resolver);
+}
+
+/// The resolver used for names in address strings.
+pub fn default_resolver() -> Arc<dyn VsockResolver> {
+    if let Some(resolver) = &*DEFAULT_RESOLVER.read().unwrap_or_else(|e| e.into_inner()) {
+        return resolver.clone();
+    }
+    DEFAULT_RESOLVER
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(|| Arc::new(FileResolver::new(DEFAULT_HOSTS_FILE)))
+        .clone()
+}
+
+/// A resolver backed by a fixed map of names to CIDs.
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, u32>,
+}
+
+impl StaticResolver {
+    /// Create an empty resolver.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map `name` to `cid`.
+    pub fn with_host(mut self, name: impl Into<String>, cid: u32) -> Self {
+        self.insert(name, cid);
+        self
+    }
+
+    /// Map `name` to `cid`, returning the CID it was previously mapped to.
+    pub fn insert(&mut self, name: impl Into<String>, cid: u32) -> Option<u32> {
+        self.hosts.insert(name.into(), cid)
+    }
+}
+
+impl<N: Into<String>> FromIterator<(N, u32)> for StaticResolver {
+    fn from_iter<I: IntoIterator<Item = (N, u32)>>(iter: I) -> Self {
+        Self {
+            hosts: iter
+                .into_iter()
+                .map(|(name, cid)| (name.into(), cid))
+                .collect(),
+        }
+    }
+}
+
+impl VsockResolver for StaticResolver {
+    fn resolve(&self, name: &str) -> io::Result<Option<u32>> {
+        Ok(self.hosts.get(name).copied())
+    }
+}
+
+#[derive(Debug)]
+struct HostsCache {
+    contents: String,
+    hosts: HashMap<String, u32>,
+}
+
+/// A resolver reading names from a file in the style of `/etc/hosts`.
+///
+/// Each line holds a CID followed by one or more names; everything after a
+/// `#` is ignored:
+///
+/// ```text
+/// # cid  names
+/// 3      builder builder.local
+/// 4      runner
+/// ```
+///
+/// The file is read on every lookup, and parsed again whenever its contents
+/// changed. A missing file resolves no names.
+#[derive(Debug)]
+pub struct FileResolver {
+    path: PathBuf,
+    cache: Mutex<Option<HostsCache>>,
+}
+
+impl FileResolver {
+    /// Create a resolver reading `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// The file read by this resolver.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Drop the cached mapping so that the file is parsed again on next use.
+    pub fn invalidate(&self) {
+        *self.cache.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+impl VsockResolver for FileResolver {
+    fn resolve(&self, name: &str) -> io::Result<Option<u32>> {
+        // Timestamps and sizes can stay the same across a rewrite, so only
+        // the contents tell whether the file changed.
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        let stale = !matches!(&*cache, Some(cached) if cached.contents == contents);
+        if stale {
+            let hosts = parse_hosts(&contents)?;
+            *cache = Some(HostsCache { contents, hosts });
+        }
+
+        Ok(cache
+            .as_ref()
+            .and_then(|cached| cached.hosts.get(name).copied()))
+    }
+}
+
+fn parse_hosts(contents: &str) -> io::Result<HashMap<String, u32>> {
+    let mut hosts = HashMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        let cid = match fields.next() {
+            Some(cid) => cid,
+            None => continue,
+        };
+        let cid = parse_cid(cid).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid CID `{}` on line {}", cid, number + 1),
+            )
+        })?;
+        for name in fields {
+            // Like /etc/hosts, the first entry for a name wins.
+            hosts.entry(name.to_owned()).or_insert(cid);
+        }
+    }
+    Ok(hosts)
+}
@@ -0,0 +1,173 @@
+//! Transfers between files and streams that do not copy the data through
+//! user space, using `sendfile` and `splice`.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::task::Poll;
+
+use futures::future::poll_fn;
+use futures::ready;
+use libc::*;
+use tokio::io::{Interest, ReadBuf};
+
+use crate::sys::{self, PIPE_SIZE};
+use crate::VsockStream;
+
+/// The most `sendfile` transfers in one call.
+const MAX_SENDFILE: u64 = 0x7fff_f000;
+
+/// `offset` as a file offset, which the kernel takes as signed.
+fn to_off_t(offset: u64) -> Result<off_t> {
+    off_t::try_from(offset)
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "file offset out of range"))
+}
+
+impl VsockStream {
+    /// Send `len` bytes of `file` starting at `offset`, without copying them
+    /// through user space.
+    ///
+    /// Returns the number of bytes sent, which is less than `len` if the end
+    /// of the file was reached. The file position is not changed. Reading the
+    /// file blocks the current thread, as for any regular file.
+    ///
+    /// ```no_run
+    /// # async fn run(stream: tokio_vsock::VsockStream) -> std::io::Result<()> {
+    /// let layer = std::fs::File::open("layer.tar")?;
+    /// let len = layer.metadata()?.len();
+    /// stream.send_file(&layer, 0, len).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_file(&self, file: &File, offset: u64, len: u64) -> Result<u64> {
+        let mut file_offset = to_off_t(offset)?;
+        let mut sent = 0;
+        while sent < len {
+            let count = (len - sent).min(MAX_SENDFILE) as usize;
+            let result = poll_fn(|cx| {
+                self.poll_io(cx, Interest::WRITABLE, |inner| {
+                    let ret = unsafe {
+                        sendfile(inner.as_raw_fd(), file.as_raw_fd(), &mut file_offset, count)
+                    };
+                    if ret < 0 {
+                        Err(Error::last_os_error())
+                    } else {
+                        Ok(ret as u64)
+                    }
+                })
+            })
+            .await;
+            match result {
+                Ok(0) => break,
+                Ok(n) => {
+                    sent += n;
+                    self.touch();
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Receive up to `len` bytes into `file` starting at `offset`, moving them
+    /// through a pipe with `splice` instead of copying them through user
+    /// space.
+    ///
+    /// Returns the number of bytes received, which is less than `len` if the
+    /// peer shut down the connection first. The file position is not
+    /// changed. Kernels that cannot splice from Virtio sockets fall back to
+    /// copying.
+    pub async fn recv_to_file(&self, file: &File, offset: u64, len: u64) -> Result<u64> {
+        let mut file_offset: loff_t = to_off_t(offset)?;
+        let (pipe_rd, pipe_wr) = sys::pipe(0)?;
+        let mut received = 0;
+        while received < len {
+            // The pipe is empty and gets filled at most to its capacity, so
+            // neither splice waits for it.
+            let count = (len - received).min(PIPE_SIZE as u64) as usize;
+            let result = poll_fn(|cx| {
+                self.poll_io(cx, Interest::READABLE, |inner| {
+                    let ret = unsafe {
+                        splice(
+                            inner.as_raw_fd(),
+                            ptr::null_mut(),
+                            pipe_wr.as_raw_fd(),
+                            ptr::null_mut(),
+                            count,
+                            SPLICE_F_MOVE | SPLICE_F_NONBLOCK,
+                        )
+                    };
+                    if ret < 0 {
+                        Err(Error::last_os_error())
+                    } else {
+                        Ok(ret as usize)
+                    }
+                })
+            })
+            .await;
+            match result {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.touch();
+                    let mut left = n;
+                    while left > 0 {
+                        let ret = unsafe {
+                            splice(
+                                pipe_rd.as_raw_fd(),
+                                ptr::null_mut(),
+                                file.as_raw_fd(),
+                                &mut file_offset,
+                                left,
+                                SPLICE_F_MOVE,
+                            )
+                        };
+                        if ret < 0 {
+                            let err = Error::last_os_error();
+                            if err.kind() == ErrorKind::Interrupted {
+                                continue;
+                            }
+                            return Err(err);
+                        }
+                        if ret == 0 {
+                            return Err(ErrorKind::WriteZero.into());
+                        }
+                        left -= ret as usize;
+                    }
+                    received += n as u64;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) if err.raw_os_error() == Some(EINVAL) => {
+                    let offset = file_offset as u64;
+                    return Ok(received + self.copy_to_file(file, offset, len - received).await?);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(received)
+    }
+
+    async fn copy_to_file(&self, file: &File, mut offset: u64, len: u64) -> Result<u64> {
+        let mut buf = vec![0u8; PIPE_SIZE];
+        let mut received = 0;
+        while received < len {
+            let count = (len - received).min(PIPE_SIZE as u64) as usize;
+            let n = poll_fn(|cx| {
+                let mut read_buf = ReadBuf::new(&mut buf[..count]);
+                ready!(self.poll_read_priv(cx, &mut read_buf))?;
+                Poll::Ready(Ok::<_, Error>(read_buf.filled().len()))
+            })
+            .await?;
+            if n == 0 {
+                break;
+            }
+            file.write_all_at(&buf[..n], offset)?;
+            offset += n as u64;
+            received += n as u64;
+        }
+        Ok(received)
+    }
+}
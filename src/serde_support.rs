@@ -0,0 +1,164 @@
+#![cfg(feature = "serde")]
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::SystemTime;
+
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::addr::parse_cid;
+use crate::{VsockConnectInfo, VsockEndpoint, VsockFlags, VsockTransport};
+
+/// Serialized as `vsock://<cid>:<port>` for human-readable formats and as a
+/// `{ cid, port }` struct otherwise.
+impl Serialize for VsockEndpoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            let mut state = serializer.serialize_struct("VsockEndpoint", 2)?;
+            state.serialize_field("cid", &self.cid())?;
+            state.serialize_field("port", &self.port())?;
+            state.end()
+        }
+    }
+}
+
+/// Deserialized from either the string form accepted by
+/// [`FromStr`](std::str::FromStr) or a `{ cid, port }` struct, where the CID may
+/// also be one of the symbolic names.
+impl<'de> Deserialize<'de> for VsockEndpoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const FIELDS: &[&str] = &["cid", "port"];
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(EndpointVisitor)
+        } else {
+            deserializer.deserialize_struct("VsockEndpoint", FIELDS, EndpointVisitor)
+        }
+    }
+}
+
+struct EndpointVisitor;
+
+impl<'de> Visitor<'de> for EndpointVisitor {
+    type Value = VsockEndpoint;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a vsock address string or a { cid, port } struct")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let cid: Cid = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let port = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(VsockEndpoint::new(cid.0, port))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut cid: Option<Cid> = None;
+        let mut port = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "cid" if cid.is_none() => cid = Some(map.next_value()?),
+                "port" if port.is_none() => port = Some(map.next_value()?),
+                "cid" | "port" => {
+                    return Err(de::Error::custom(format!("duplicate field `{}`", key)))
+                }
+                _ => return Err(de::Error::unknown_field(&key, &["cid", "port"])),
+            }
+        }
+        let cid = cid.ok_or_else(|| de::Error::missing_field("cid"))?;
+        let port = port.ok_or_else(|| de::Error::missing_field("port"))?;
+        Ok(VsockEndpoint::new(cid.0, port))
+    }
+}
+
+/// A CID given either as a number or as a symbolic name.
+struct Cid(u32);
+
+impl<'de> Deserialize<'de> for Cid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CidVisitor;
+
+        impl Visitor<'_> for CidVisitor {
+            type Value = Cid;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a CID number or one of hypervisor, local, host or any")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .map(Cid)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .map(Cid)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                parse_cid(v)
+                    .map(Cid)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(CidVisitor)
+        } else {
+            deserializer.deserialize_u32(CidVisitor)
+        }
+    }
+}
+
+/// The serialized form of a [`VsockConnectInfo`].
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "VsockConnectInfo")]
+pub(crate) struct ConnectInfoRepr {
+    peer_addr: Option<VsockEndpoint>,
+    #[serde(default)]
+    peer_flags: u8,
+    local_addr: Option<VsockEndpoint>,
+    connected_at: SystemTime,
+    connection_id: u64,
+    transport: VsockTransport,
+}
+
+impl From<VsockConnectInfo> for ConnectInfoRepr {
+    fn from(info: VsockConnectInfo) -> Self {
+        Self {
+            peer_addr: info.peer_addr().map(Into::into),
+            peer_flags: info.peer_flags().bits(),
+            local_addr: info.local_addr().map(Into::into),
+            connected_at: info.connected_at(),
+            connection_id: info.connection_id(),
+            transport: info.transport(),
+        }
+    }
+}
+
+impl From<ConnectInfoRepr> for VsockConnectInfo {
+    fn from(repr: ConnectInfoRepr) -> Self {
+        VsockConnectInfo::from_parts(
+            repr.local_addr.map(Into::into),
+            repr.peer_addr.map(Into::into),
+            VsockFlags::from_bits(repr.peer_flags),
+            repr.connected_at,
+            repr.connection_id,
+            repr.transport,
+        )
+    }
+}
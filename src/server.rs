@@ -0,0 +1,249 @@
+//! A server dispatching connections on several ports to their handlers.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::io::{ErrorKind, Result};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::future::{self, poll_fn, BoxFuture, Either, FutureExt};
+use tokio::task::JoinSet;
+
+use crate::{
+    AcceptFilter, VsockAddr, VsockConnectInfo, VsockListener, VsockMultiListener, VsockStream,
+    VMADDR_CID_ANY,
+};
+
+type Handler = Arc<dyn Fn(VsockStream, VsockConnectInfo) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// A server running a handler for each connection accepted on its ports.
+///
+/// Every connection runs in its own task, owned by the server. A handler that
+/// panics only takes down its own connection.
+///
+/// ```no_run
+/// # async fn serve(shutdown: impl std::future::Future<Output = ()>) -> std::io::Result<()> {
+/// use std::time::Duration;
+/// use tokio::io::AsyncWriteExt;
+/// use tokio_vsock::{VsockConnectInfo, VsockServer, VsockStream};
+///
+/// async fn hello(mut stream: VsockStream, info: VsockConnectInfo) {
+///     let _ = stream.write_all(b"hello\n").await;
+/// }
+///
+/// VsockServer::new()
+///     .route(8000, hello)
+///     .idle_timeout(Duration::from_secs(60))
+///     .serve_with_shutdown(shutdown, Duration::from_secs(5))
+///     .await
+/// # }
+/// ```
+pub struct VsockServer {
+    cid: u32,
+    routes: BTreeMap<u32, Handler>,
+    idle_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+    filter: Option<AcceptFilter>,
+}
+
+impl VsockServer {
+    /// Create a server without routes, listening on `VMADDR_CID_ANY`.
+    pub fn new() -> Self {
+        Self {
+            cid: VMADDR_CID_ANY,
+            routes: BTreeMap::new(),
+            idle_timeout: None,
+            total_timeout: None,
+            filter: None,
+        }
+    }
+
+    /// Listen on `cid` instead of `VMADDR_CID_ANY`.
+    pub fn cid(mut self, cid: u32) -> Self {
+        self.cid = cid;
+        self
+    }
+
+    /// Run `handler` for each connection accepted on `port`, replacing any
+    /// handler previously routed to the port.
+    pub fn route<F, Fut>(mut self, port: u32, handler: F) -> Self
+    where
+        F: Fn(VsockStream, VsockConnectInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.routes.insert(
+            port,
+            Arc::new(move |stream, info| handler(stream, info).boxed()),
+        );
+        self
+    }
+
+    /// Close connections that neither read nor write anything for `timeout`.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Close connections that are still open `timeout` after they were
+    /// accepted.
+    pub fn total_timeout(mut self, timeout: Duration) -> Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+
+    /// Restrict the connections accepted on all ports.
+    ///
+    /// See [`AcceptFilter`] for details.
+    pub fn accept_filter(mut self, filter: AcceptFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Bind the routed ports and serve connections forever.
+    ///
+    /// Fails if any of the ports cannot be bound, or if no port is routed.
+    pub async fn serve(self) -> Result<()> {
+        self.serve_with_shutdown(future::pending(), Duration::ZERO)
+            .await
+    }
+
+    /// Bind the routed ports and serve connections until `signal` completes.
+    ///
+    /// Once the signal completes the ports are closed and the open connections
+    /// get up to `drain` to finish. Connections still open after that are
+    /// aborted.
+    ///
+    /// Fails if any of the ports cannot be bound, or if no port is routed.
+    pub async fn serve_with_shutdown<F>(self, signal: F, drain: Duration) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let cid = self.cid;
+        let mut listener = VsockMultiListener::from_listeners(
+            self.routes
+                .keys()
+                .map(|&port| VsockListener::bind(VsockAddr::new(cid, port)))
+                .collect::<Result<Vec<_>>>()?,
+        )?;
+        if let Some(filter) = self.filter.clone() {
+            listener.set_accept_filter(filter);
+        }
+
+        let mut connections = JoinSet::new();
+        let mut signal = Box::pin(signal);
+        loop {
+            let accepted = poll_fn(|cx| {
+                // Reap finished connections so that the set does not grow.
+                while let Poll::Ready(Some(_)) = connections.poll_join_next(cx) {}
+                if signal.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+                listener.poll_accept(cx).map(Some)
+            })
+            .await;
+
+            match accepted {
+                Some(Ok((stream, _))) => {
+                    let port = stream.connect_info().local_addr().map(|addr| addr.port());
+                    if let Some(handler) = port.and_then(|port| self.routes.get(&port)) {
+                        connections.spawn(self.run(handler, stream));
+                    }
+                }
+                Some(Err(err)) => {
+                    // Keep reaping connections and watching the signal while
+                    // backing off.
+                    let mut backoff = Box::pin(handle_accept_error(err));
+                    let shutdown = poll_fn(|cx| {
+                        while let Poll::Ready(Some(_)) = connections.poll_join_next(cx) {}
+                        if signal.as_mut().poll(cx).is_ready() {
+                            return Poll::Ready(true);
+                        }
+                        backoff.as_mut().poll(cx).map(|()| false)
+                    })
+                    .await;
+                    if shutdown {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        drop(listener);
+        let drained = tokio::time::timeout(drain, async {
+            while connections.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            connections.shutdown().await;
+        }
+        Ok(())
+    }
+
+    fn run(&self, handler: &Handler, stream: VsockStream) -> impl Future<Output = ()> {
+        let activity = self.idle_timeout.map(|_| stream.activity());
+        let idle_timeout = self.idle_timeout;
+        let total_timeout = self.total_timeout;
+        let info = stream.connect_info().clone();
+        let connection = handler(stream, info);
+
+        async move {
+            let idle = match (activity, idle_timeout) {
+                (Some(activity), Some(timeout)) => Either::Left(activity.expired(timeout)),
+                _ => Either::Right(future::pending()),
+            };
+            let connection = future::select(connection, Box::pin(idle));
+            // Dropping the handler closes the stream.
+            match total_timeout {
+                Some(timeout) => {
+                    let _ = tokio::time::timeout(timeout, connection).await;
+                }
+                None => {
+                    connection.await;
+                }
+            }
+        }
+    }
+}
+
+impl Default for VsockServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for VsockServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VsockServer")
+            .field("cid", &self.cid)
+            .field("ports", &self.routes.keys().collect::<Vec<_>>())
+            .field("idle_timeout", &self.idle_timeout)
+            .field("total_timeout", &self.total_timeout)
+            .field("filter", &self.filter)
+            .finish()
+    }
+}
+
+/// Wait before accepting again after `err`, unless it only concerns the
+/// connection being accepted.
+pub(crate) async fn handle_accept_error(err: std::io::Error) {
+    if matches!(
+        err.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset
+    ) {
+        return;
+    }
+
+    // [From `hyper::Server` in 0.14](https://github.com/hyperium/hyper/blob/v0.14.27/src/server/tcp.rs#L186)
+    //
+    // > A possible scenario is that the process has hit the max open files
+    // > allowed, and so trying to accept a new connection will fail with
+    // > `EMFILE`. In some cases, it's preferable to just wait for some time, if
+    // > the application will likely close some files (or connections), and try
+    // > to accept the connection again. If this option is `true`, the error
+    // > will be logged at the `error` level, since it is still a big deal,
+    // > and then the listener will sleep for 1 second.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
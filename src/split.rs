@@ -10,7 +10,9 @@ use crate::VsockStream;
 use std::fmt;
 use std::io;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 /// Splits a ``VsockStream`` into a readable half and a writeable half
 pub fn split(stream: &mut VsockStream) -> (ReadHalf<'_>, WriteHalf<'_>) {
@@ -20,11 +22,42 @@ pub fn split(stream: &mut VsockStream) -> (ReadHalf<'_>, WriteHalf<'_>) {
 }
 
 /// The readable half of a value returned from [`split`](split()).
+///
+/// With the `futures-io` feature, wrap it in [`futures::io::BufReader`] for
+/// `AsyncBufRead`.
 pub struct ReadHalf<'a>(&'a VsockStream);
 
 /// The writable half of a value returned from [`split`](split()).
 pub struct WriteHalf<'a>(&'a VsockStream);
 
+impl ReadHalf<'_> {
+    /// Fail reads that make no progress for `timeout`, see
+    /// [`VsockStream::set_read_timeout`].
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        self.0.set_read_timeout(timeout)
+    }
+
+    /// Close the connection when an operation is waiting and it was idle for
+    /// `timeout`, see [`VsockStream::set_idle_timeout`].
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        self.0.set_idle_timeout(timeout)
+    }
+}
+
+impl WriteHalf<'_> {
+    /// Fail writes that make no progress for `timeout`, see
+    /// [`VsockStream::set_write_timeout`].
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) {
+        self.0.set_write_timeout(timeout)
+    }
+
+    /// Close the connection when an operation is waiting and it was idle for
+    /// `timeout`, see [`VsockStream::set_idle_timeout`].
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        self.0.set_idle_timeout(timeout)
+    }
+}
+
 impl AsyncRead for ReadHalf<'_> {
     fn poll_read(
         self: Pin<&mut Self>,
@@ -44,39 +77,45 @@ impl AsyncWrite for WriteHalf<'_> {
         self.0.poll_write_priv(cx, buf)
     }
 
-    #[inline]
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        // Not buffered so flush is a No-op
-        Poll::Ready(Ok(()))
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.0.poll_flush_priv(cx)
     }
 
-    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        // TODO: This could maybe block?
-        self.0.shutdown(std::net::Shutdown::Write)?;
-        Poll::Ready(Ok(()))
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.0.poll_shutdown_priv(cx)
     }
 }
 
 pub fn split_owned(stream: VsockStream) -> (OwnedReadHalf, OwnedWriteHalf) {
-    let (rd, wr) = tokio::io::split(stream);
-    (OwnedReadHalf { inner: rd }, OwnedWriteHalf { inner: wr })
+    // Reads and writes only need a shared reference to the stream, which
+    // keeps a separate readiness state for each direction.
+    let inner = Arc::new(stream);
+    (
+        OwnedReadHalf {
+            inner: inner.clone(),
+        },
+        OwnedWriteHalf { inner },
+    )
 }
 
 /// The readable half of a value returned from [`split_owned`](split_owned()).
+///
+/// With the `futures-io` feature, wrap it in [`futures::io::BufReader`] for
+/// `AsyncBufRead`.
 pub struct OwnedReadHalf {
-    inner: tokio::io::ReadHalf<VsockStream>,
+    inner: Arc<VsockStream>,
 }
 
 /// The writable half of a value returned from [`split_owned`](split_owned()).
 pub struct OwnedWriteHalf {
-    inner: tokio::io::WriteHalf<VsockStream>,
+    inner: Arc<VsockStream>,
 }
 
 impl OwnedReadHalf {
     /// Checks if this `ReadHalf` and some `WriteHalf` were split from the same
     /// stream.
     pub fn is_pair_of(&self, other: &OwnedWriteHalf) -> bool {
-        self.inner.is_pair_of(&other.inner)
+        Arc::ptr_eq(&self.inner, &other.inner)
     }
 
     /// Reunites with a previously split `WriteHalf`.
@@ -89,7 +128,48 @@ impl OwnedReadHalf {
     /// of the two halves.
     #[track_caller]
     pub fn unsplit(self, wr: OwnedWriteHalf) -> VsockStream {
-        self.inner.unsplit(wr.inner)
+        assert!(
+            self.is_pair_of(&wr),
+            "Unrelated `OwnedWriteHalf` passed to `OwnedReadHalf::unsplit`"
+        );
+        drop(wr);
+        Arc::try_unwrap(self.inner).expect("the halves are the only references to the stream")
+    }
+
+    /// Fail reads that make no progress for `timeout`, see
+    /// [`VsockStream::set_read_timeout`].
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    /// The number of received bytes that can be read without waiting, see
+    /// [`VsockStream::unread_bytes`].
+    pub fn unread_bytes(&self) -> io::Result<usize> {
+        self.inner.unread_bytes()
+    }
+
+    /// Close the connection when an operation is waiting and it was idle for
+    /// `timeout`, see [`VsockStream::set_idle_timeout`].
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        self.inner.set_idle_timeout(timeout)
+    }
+
+    /// Whether the peer closed the connection, see
+    /// [`VsockStream::is_read_closed`].
+    pub fn is_read_closed(&self) -> io::Result<bool> {
+        self.inner.is_read_closed()
+    }
+
+    /// Wait until the peer closed the connection, see
+    /// [`VsockStream::closed`].
+    pub async fn closed(&self) -> io::Result<()> {
+        self.inner.closed().await
+    }
+
+    /// Poll for the peer closing the connection, see
+    /// [`VsockStream::closed`].
+    pub fn poll_read_closed(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.inner.poll_read_closed(cx)
     }
 }
 
@@ -97,38 +177,77 @@ impl OwnedWriteHalf {
     /// Checks if this `WriteHalf` and some `ReadHalf` were split from the same
     /// stream.
     pub fn is_pair_of(&self, other: &OwnedReadHalf) -> bool {
-        self.inner.is_pair_of(&other.inner)
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Fail writes that make no progress for `timeout`, see
+    /// [`VsockStream::set_write_timeout`].
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) {
+        self.inner.set_write_timeout(timeout)
+    }
+
+    /// The number of written bytes the peer has not received yet, see
+    /// [`VsockStream::unsent_bytes`].
+    pub fn unsent_bytes(&self) -> io::Result<usize> {
+        self.inner.unsent_bytes()
+    }
+
+    /// Make flushing wait until the peer received all written data, see
+    /// [`VsockStream::set_drain_on_flush`].
+    pub fn set_drain_on_flush(&self, drain: bool) {
+        self.inner.set_drain_on_flush(drain)
+    }
+
+    /// Close the connection when an operation is waiting and it was idle for
+    /// `timeout`, see [`VsockStream::set_idle_timeout`].
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        self.inner.set_idle_timeout(timeout)
+    }
+
+    /// Whether the peer closed the connection, see
+    /// [`VsockStream::is_read_closed`].
+    pub fn is_read_closed(&self) -> io::Result<bool> {
+        self.inner.is_read_closed()
+    }
+
+    /// Wait until the peer closed the connection, see
+    /// [`VsockStream::closed`].
+    pub async fn closed(&self) -> io::Result<()> {
+        self.inner.closed().await
+    }
+
+    /// Poll for the peer closing the connection, see
+    /// [`VsockStream::closed`].
+    pub fn poll_read_closed(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.inner.poll_read_closed(cx)
     }
 }
 
 impl AsyncRead for OwnedReadHalf {
     fn poll_read(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.inner).poll_read(cx, buf)
+        self.inner.poll_read_priv(cx, buf)
     }
 }
 
 impl AsyncWrite for OwnedWriteHalf {
     fn poll_write(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
-        Pin::new(&mut self.inner).poll_write(cx, buf)
+        self.inner.poll_write_priv(cx, buf)
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        Pin::new(&mut self.inner).poll_flush(cx)
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.inner.poll_flush_priv(cx)
     }
 
-    fn poll_shutdown(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-    ) -> Poll<Result<(), io::Error>> {
-        Pin::new(&mut self.inner).poll_shutdown(cx)
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.inner.poll_shutdown_priv(cx)
     }
 }
 
@@ -43,74 +43,179 @@
  * limitations under the License.
  */
 
-use std::io::{Error, Read, Result, Write};
+use std::io::{Read, Result, Write};
 use std::net::Shutdown;
 use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
-use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
 
+use crate::addr::{lookup, no_addresses, ToVsockAddrs};
+use crate::filter::ConnectionSlot;
+use crate::flags::{peer_flags, VsockFlags};
 use crate::split::{split_owned, OwnedReadHalf, OwnedWriteHalf, ReadHalf, WriteHalf};
-use crate::VsockAddr;
+use crate::sys;
+use crate::timeout::{Activity, Expired, Timeouts, Timer};
+use crate::zerocopy::ZeroCopy;
+use crate::{VsockAddr, VsockConnectInfo};
 use futures::ready;
-use libc::*;
-use std::mem::{self, size_of};
+use std::future::Future;
+use std::mem;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::{Mutex, MutexGuard, OnceLock};
 use std::task::{Context, Poll};
+use std::time::Duration;
 use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// The longest interval between checks of the send queue while draining it.
+const MAX_DRAIN_BACKOFF: Duration = Duration::from_millis(32);
 
 /// An I/O object representing a Virtio socket connected to a remote endpoint.
+///
+/// With the `futures-io` feature it also implements the [`futures::io`]
+/// reading and writing traits; wrap it in [`futures::io::BufReader`] for
+/// `AsyncBufRead`.
 #[derive(Debug)]
 pub struct VsockStream {
     inner: AsyncFd<vsock::VsockStream>,
+    slot: Option<ConnectionSlot>,
+    info: VsockConnectInfo,
+    /// Created once idle time is tracked, so that other streams do not read
+    /// the clock on every read and write.
+    activity: OnceLock<Arc<Activity>>,
+    /// Allocated once a timeout is set, keeping streams without timeouts small.
+    timeouts: OnceLock<Box<Timeouts>>,
+    /// A duplicate of the socket registered separately, so waiting for the
+    /// peer to close can clear its readiness without affecting reads.
+    closed: OnceLock<Box<AsyncFd<OwnedFd>>>,
+    drain: OnceLock<Box<Mutex<SendQueueDrain>>>,
+    zerocopy: Option<Box<ZeroCopy>>,
+}
+
+/// Waits for the send queue to drain on flush, polling it with a growing
+/// interval since the kernel does not signal an empty queue.
+#[derive(Debug, Default)]
+struct SendQueueDrain {
+    enabled: bool,
+    backoff: Duration,
+    sleep: Option<Pin<Box<Sleep>>>,
 }
 
 impl VsockStream {
     pub fn new(connected: vsock::VsockStream) -> Result<Self> {
         connected.set_nonblocking(true)?;
+        let info = sys::connect_info(&connected);
         Ok(Self {
-            inner: AsyncFd::new(connected)?,
+            // Safety: the vsock socket owns its fd, which stays open until the
+            // `AsyncFd` is dropped or consumed via `into_inner`.
+            inner: unsafe { AsyncFd::register(connected) }?,
+            slot: None,
+            info,
+            activity: OnceLock::new(),
+            timeouts: OnceLock::new(),
+            closed: OnceLock::new(),
+            drain: OnceLock::new(),
+            zerocopy: None,
         })
     }
 
-    /// Open a connection to a remote host.
-    pub async fn connect(addr: VsockAddr) -> Result<Self> {
-        let socket = unsafe { socket(AF_VSOCK, SOCK_STREAM, 0) };
-        if socket < 0 {
-            return Err(Error::last_os_error());
-        }
+    pub(crate) fn with_slot(mut self, slot: Option<ConnectionSlot>) -> Self {
+        self.slot = slot;
+        self
+    }
 
-        if unsafe { fcntl(socket, F_SETFL, O_NONBLOCK) } < 0 {
-            let _ = unsafe { close(socket) };
-            return Err(Error::last_os_error());
-        }
+    /// Take the accept filter slot, to move it along with the socket.
+    #[cfg(feature = "io-uring")]
+    pub(crate) fn take_slot(&mut self) -> Option<ConnectionSlot> {
+        self.slot.take()
+    }
 
-        if unsafe { fcntl(socket, F_SETFD, FD_CLOEXEC) } < 0 {
-            let _ = unsafe { close(socket) };
-            return Err(Error::last_os_error());
+    /// The time of the last read or write, tracked from the first call on.
+    pub(crate) fn activity(&self) -> Arc<Activity> {
+        self.activity
+            .get_or_init(|| Arc::new(Activity::new()))
+            .clone()
+    }
+
+    /// Record a read or write, if idle time is tracked.
+    pub(crate) fn touch(&self) {
+        if let Some(activity) = self.activity.get() {
+            activity.touch();
         }
+    }
+
+    pub(crate) fn async_fd(&self) -> &AsyncFd<vsock::VsockStream> {
+        &self.inner
+    }
+
+    pub(crate) fn zerocopy_state(&mut self) -> &mut ZeroCopy {
+        self.zerocopy.get_or_insert_with(Box::default)
+    }
 
-        if unsafe {
-            connect(
-                socket,
-                &addr as *const _ as *const sockaddr,
-                size_of::<sockaddr_vm>() as socklen_t,
-            )
-        } < 0
-        {
-            let err = Error::last_os_error();
-            if let Some(os_err) = err.raw_os_error() {
-                // Connect hasn't finished, that's fine.
-                if os_err != EINPROGRESS {
-                    // Close the socket if we hit an error, ignoring the error
-                    // from closing since we can't pass back two errors.
-                    let _ = unsafe { close(socket) };
-                    return Err(err);
+    /// Wait for `interest` and run `op` on the socket until it does not fail
+    /// with `WouldBlock`, enforcing the read or write timeouts meanwhile.
+    pub(crate) fn poll_io<T>(
+        &self,
+        cx: &mut Context<'_>,
+        interest: Interest,
+        mut op: impl FnMut(&vsock::VsockStream) -> Result<T>,
+    ) -> Poll<Result<T>> {
+        let timer = if interest.is_readable() {
+            Timeouts::read
+        } else {
+            Timeouts::write
+        };
+        loop {
+            let ready = if interest.is_readable() {
+                self.inner.poll_read_ready(cx)
+            } else {
+                self.inner.poll_write_ready(cx)
+            };
+            let mut guard = match ready {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return self.poll_timeouts(cx, timer),
+            };
+            if let Ok(result) = guard.try_io(|inner| op(inner.get_ref())) {
+                if result.is_ok() {
+                    if let Some(timeouts) = self.timeouts.get() {
+                        timer(timeouts).done();
+                    }
                 }
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    /// Open a connection to a remote host.
+    ///
+    /// If `addr` yields several addresses, they are tried in turn until a
+    /// connection succeeds, and the error of the last attempt is returned
+    /// otherwise.
+    pub async fn connect<A: ToVsockAddrs>(addr: A) -> Result<Self> {
+        Self::connect_with_flags(addr, VsockFlags::empty()).await
+    }
+
+    /// Open a connection to a remote host, setting `flags` in the destination
+    /// address.
+    ///
+    /// Kernels without support for the flags fail with
+    /// [`ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported); see
+    /// [`supports_flag_to_host`](crate::supports_flag_to_host).
+    pub async fn connect_with_flags<A: ToVsockAddrs>(addr: A, flags: VsockFlags) -> Result<Self> {
+        let mut last_err = None;
+        for addr in lookup(addr).await? {
+            match Self::connect_addr(addr, flags).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err),
             }
         }
+        Err(last_err.unwrap_or_else(no_addresses))
+    }
 
-        let stream = unsafe { vsock::VsockStream::from_raw_fd(socket) };
-        let stream = Self::new(stream)?;
+    async fn connect_addr(addr: VsockAddr, flags: VsockFlags) -> Result<Self> {
+        let mut stream = Self::new(sys::start_connect(addr, flags)?)?;
 
         loop {
             // Checks if the connection failed or not.
@@ -118,31 +223,16 @@ impl VsockStream {
             // Scope the readiness guard tightly so we can return `stream` on success.
             let conn_check = {
                 let mut guard = stream.inner.writable().await?;
-                guard.try_io(|fd| {
-                    let mut sock_err: c_int = 0;
-                    let mut sock_err_len: socklen_t = size_of::<c_int>() as socklen_t;
-                    let err = unsafe {
-                        getsockopt(
-                            fd.as_raw_fd(),
-                            SOL_SOCKET,
-                            SO_ERROR,
-                            &mut sock_err as *mut _ as *mut c_void,
-                            &mut sock_err_len as *mut socklen_t,
-                        )
-                    };
-                    if err < 0 {
-                        return Err(Error::last_os_error());
-                    }
-                    if sock_err == 0 {
-                        Ok(())
-                    } else {
-                        Err(Error::from_raw_os_error(sock_err))
-                    }
-                })
+                guard.try_io(|fd| sys::take_error(fd.as_raw_fd()))
             };
 
             match conn_check {
-                Ok(Ok(_)) => return Ok(stream),
+                Ok(Ok(_)) => {
+                    let local_addr = stream.local_addr().ok();
+                    let peer_flags = stream.peer_flags().unwrap_or(flags);
+                    stream.info.set_addrs(local_addr, Some(addr), peer_flags);
+                    return Ok(stream);
+                }
                 Ok(Err(err)) => return Err(err),
                 Err(_would_block) => continue,
             }
@@ -159,11 +249,214 @@ impl VsockStream {
         self.inner.get_ref().peer_addr()
     }
 
+    /// The flags of the remote address, e.g. [`VsockFlags::TO_HOST`] for
+    /// connections forwarded through the host.
+    pub fn peer_flags(&self) -> Result<VsockFlags> {
+        peer_flags(self.as_raw_fd())
+    }
+
+    /// The connection info captured when this connection was accepted or
+    /// established.
+    pub fn connect_info(&self) -> &VsockConnectInfo {
+        &self.info
+    }
+
     /// Shuts down the read, write, or both halves of this connection.
     pub fn shutdown(&self, how: Shutdown) -> Result<()> {
         self.inner.get_ref().shutdown(how)
     }
 
+    /// Close the connection after the peer has seen all written data.
+    ///
+    /// This shuts down the writing half, waiting for the send queue to drain
+    /// first if [`drain_on_flush`](Self::set_drain_on_flush) is enabled, and
+    /// then discards incoming data until the peer closes its end as well.
+    /// Returns whether the peer did so within `timeout`.
+    ///
+    /// ```no_run
+    /// # async fn run(stream: tokio_vsock::VsockStream) -> std::io::Result<()> {
+    /// use std::time::Duration;
+    ///
+    /// if !stream.close_gracefully(Duration::from_secs(5)).await? {
+    ///     eprintln!("peer did not close the connection in time");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn close_gracefully(mut self, timeout: Duration) -> Result<bool> {
+        let close = async {
+            futures::future::poll_fn(|cx| self.poll_shutdown_priv(cx)).await?;
+            let mut buf = [0u8; 4096];
+            while tokio::io::AsyncReadExt::read(&mut self, &mut buf).await? > 0 {}
+            Ok(())
+        };
+        match tokio::time::timeout(timeout, close).await {
+            Ok(result) => result.map(|()| true),
+            Err(_elapsed) => Ok(false),
+        }
+    }
+
+    /// Set the `SO_LINGER` option, making the final close of the socket wait
+    /// up to `duration` for unsent data to be sent.
+    ///
+    /// The option has a granularity of seconds, `duration` is rounded up to
+    /// whole seconds. `None` disables lingering, the default. Only some Virtio socket
+    /// transports honor this option, and closing a socket blocks the thread
+    /// while lingering, so prefer [`close_gracefully`](Self::close_gracefully)
+    /// in async code.
+    pub fn set_linger(&self, duration: Option<Duration>) -> Result<()> {
+        sys::set_linger(self.as_raw_fd(), duration)
+    }
+
+    /// The `SO_LINGER` option, see [`set_linger`](Self::set_linger).
+    pub fn linger(&self) -> Result<Option<Duration>> {
+        sys::linger(self.as_raw_fd())
+    }
+
+    /// Fail reads that make no progress for `timeout` with
+    /// [`ErrorKind::TimedOut`](std::io::ErrorKind::TimedOut).
+    ///
+    /// The timer starts when a read has to wait for data, and restarts
+    /// whenever a waiting read is polled without being woken by the stream,
+    /// since that cannot be told apart from a new read. `None`, the default,
+    /// lets reads wait indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        self.timeouts_or_default().read().set_timeout(timeout);
+    }
+
+    /// The timeout of reads, see [`set_read_timeout`](Self::set_read_timeout).
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.timeouts.get().and_then(|t| t.read().timeout())
+    }
+
+    /// Fail writes that make no progress for `timeout` with
+    /// [`ErrorKind::TimedOut`](std::io::ErrorKind::TimedOut).
+    ///
+    /// The timer starts when a write has to wait for buffer space, and
+    /// restarts like the [read timeout](Self::set_read_timeout). `None`, the
+    /// default, lets writes wait indefinitely.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) {
+        self.timeouts_or_default().write().set_timeout(timeout);
+    }
+
+    /// The timeout of writes, see [`set_write_timeout`](Self::set_write_timeout).
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.timeouts.get().and_then(|t| t.write().timeout())
+    }
+
+    /// Close the connection when a read or write is waiting and nothing was
+    /// read or written for `timeout`.
+    ///
+    /// The idle timer only runs while a read or write is waiting, so a stream
+    /// that nobody polls stays open; [`VsockServer::idle_timeout`] closes
+    /// connections regardless of what their handler does. Once expired, the
+    /// waiting read or write fails with
+    /// [`ErrorKind::TimedOut`](std::io::ErrorKind::TimedOut) and the
+    /// connection is shut down in both directions: later reads return end of
+    /// file, later writes fail, and the peer sees the connection shut down.
+    /// `None`, the default, disables the idle timeout.
+    ///
+    /// [`VsockServer::idle_timeout`]: crate::VsockServer::idle_timeout
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        if timeout.is_some() {
+            self.activity();
+        }
+        self.timeouts_or_default().set_idle_timeout(timeout);
+    }
+
+    /// The idle timeout, see [`set_idle_timeout`](Self::set_idle_timeout).
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.timeouts.get().and_then(|t| t.idle_timeout())
+    }
+
+    /// The number of written bytes the peer has not received yet.
+    ///
+    /// This uses the `SIOCOUTQ` ioctl, which older kernels do not support for
+    /// Virtio sockets.
+    pub fn unsent_bytes(&self) -> Result<usize> {
+        sys::unsent_bytes(self.as_raw_fd())
+    }
+
+    /// The number of received bytes that can be read without waiting.
+    ///
+    /// This uses the `SIOCINQ` ioctl, which older kernels do not support for
+    /// Virtio sockets.
+    pub fn unread_bytes(&self) -> Result<usize> {
+        sys::unread_bytes(self.as_raw_fd())
+    }
+
+    /// Make flushing and shutting down wait until the peer received all
+    /// written data, see [`unsent_bytes`](Self::unsent_bytes).
+    ///
+    /// Writes complete once the data was queued in the kernel, which may hold
+    /// on to it until the peer has buffer space. By default flushing does
+    /// nothing, so closing right after writing can lose queued data. The
+    /// wait is bounded by the [write timeout](Self::set_write_timeout).
+    pub fn set_drain_on_flush(&self, drain: bool) {
+        let state = self.drain.get_or_init(Box::default);
+        state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .enabled = drain;
+    }
+
+    /// Whether flushing waits for the send queue to drain, see
+    /// [`set_drain_on_flush`](Self::set_drain_on_flush).
+    pub fn drain_on_flush(&self) -> bool {
+        self.drain.get().is_some_and(|state| {
+            state
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .enabled
+        })
+    }
+
+    /// Whether the peer closed the connection or shut down its writing half.
+    ///
+    /// Unlike a read returning 0, this does not consume any data, and is
+    /// true even while data sent before the close is still unread.
+    pub fn is_read_closed(&self) -> Result<bool> {
+        sys::is_read_closed(self.as_raw_fd())
+    }
+
+    /// Wait until the peer closed the connection or shut down its writing
+    /// half, without reading from the stream.
+    ///
+    /// ```no_run
+    /// # async fn run(stream: tokio_vsock::VsockStream) -> std::io::Result<()> {
+    /// stream.closed().await?;
+    /// println!("guest {:?} went away", stream.peer_addr());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn closed(&self) -> Result<()> {
+        futures::future::poll_fn(|cx| self.poll_read_closed(cx)).await
+    }
+
+    /// Poll for the peer closing the connection, see [`closed`](Self::closed).
+    pub fn poll_read_closed(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let closed = match self.closed.get() {
+            Some(closed) => closed,
+            None => {
+                let fd = self.as_fd().try_clone_to_owned()?;
+                // Safety: the duplicate is owned by the `AsyncFd` and closed
+                // when it is dropped.
+                let registered =
+                    unsafe { AsyncFd::register_with_interest(fd, Interest::READABLE) }?;
+                let _ = self.closed.set(Box::new(registered));
+                self.closed.get().expect("registration was just set")
+            }
+        };
+        loop {
+            let mut guard = ready!(closed.poll_read_ready(cx))?;
+            if guard.ready().is_read_closed() || self.is_read_closed()? {
+                return Poll::Ready(Ok(()));
+            }
+            // Only data arrived, wait for the next event.
+            guard.clear_ready();
+        }
+    }
+
     /// Splits a single value implementing `AsyncRead + AsyncWrite` into separate
     /// `AsyncRead` and `AsyncWrite` handles.
     pub fn split(&mut self) -> (ReadHalf<'_>, WriteHalf<'_>) {
@@ -179,12 +472,84 @@ impl VsockStream {
         split_owned(self)
     }
 
+    fn timeouts_or_default(&self) -> &Timeouts {
+        self.timeouts.get_or_init(Box::default)
+    }
+
+    /// Fail an operation waiting for readiness once one of its timeouts
+    /// expired, closing the connection if it was idle.
+    fn poll_timeouts<T>(
+        &self,
+        cx: &mut Context<'_>,
+        timer: fn(&Timeouts) -> MutexGuard<'_, Timer>,
+    ) -> Poll<Result<T>> {
+        let timeouts = match self.timeouts.get() {
+            Some(timeouts) => timeouts,
+            None => return Poll::Pending,
+        };
+        let activity = self.activity.get().map(|activity| &**activity);
+        let expired = ready!(timer(timeouts).poll_expired(cx, activity));
+        if expired == Expired::Idle {
+            let _ = self.shutdown(Shutdown::Both);
+        }
+        Poll::Ready(Err(expired.into()))
+    }
+
+    fn poll_drain(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut drain = match self.drain.get() {
+            Some(drain) => drain
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            None => return Poll::Ready(Ok(())),
+        };
+        if !drain.enabled {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            if self.unsent_bytes()? == 0 {
+                drain.sleep = None;
+                drain.backoff = Duration::ZERO;
+                if let Some(timeouts) = self.timeouts.get() {
+                    timeouts.write().done();
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if let Some(sleep) = &mut drain.sleep {
+                if sleep.as_mut().poll(cx).is_pending() {
+                    drop(drain);
+                    return self.poll_timeouts(cx, Timeouts::write);
+                }
+            }
+            drain.backoff = (drain.backoff * 2).clamp(Duration::from_millis(1), MAX_DRAIN_BACKOFF);
+            drain.sleep = Some(Box::pin(tokio::time::sleep(drain.backoff)));
+        }
+    }
+
+    pub(crate) fn poll_flush_priv(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_drain(cx)
+    }
+
+    pub(crate) fn poll_shutdown_priv(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.poll_drain(cx))?;
+        self.shutdown(Shutdown::Write)?;
+        Poll::Ready(Ok(()))
+    }
+
     pub(crate) fn poll_write_priv(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
         loop {
-            let mut guard = ready!(self.inner.poll_write_ready(cx))?;
+            let mut guard = match self.inner.poll_write_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return self.poll_timeouts(cx, Timeouts::write),
+            };
 
             match guard.try_io(|inner| inner.get_ref().write(buf)) {
-                Ok(Ok(n)) => return Ok(n).into(),
+                Ok(Ok(n)) => {
+                    self.touch();
+                    if let Some(timeouts) = self.timeouts.get() {
+                        timeouts.write().done();
+                    }
+                    return Ok(n).into();
+                }
                 Ok(Err(ref e)) if e.kind() == std::io::ErrorKind::Interrupted => continue,
                 Ok(Err(e)) => return Err(e).into(),
                 Err(_would_block) => continue,
@@ -203,7 +568,10 @@ impl VsockStream {
         };
 
         loop {
-            let mut guard = ready!(self.inner.poll_read_ready(cx))?;
+            let mut guard = match self.inner.poll_read_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return self.poll_timeouts(cx, Timeouts::read),
+            };
 
             match guard.try_io(|inner| inner.get_ref().read(b)) {
                 Ok(Ok(n)) => {
@@ -211,6 +579,10 @@ impl VsockStream {
                         buf.assume_init(n);
                     }
                     buf.advance(n);
+                    self.touch();
+                    if let Some(timeouts) = self.timeouts.get() {
+                        timeouts.read().done();
+                    }
                     return Ok(()).into();
                 }
                 Ok(Err(ref e)) if e.kind() == std::io::ErrorKind::Interrupted => continue,
@@ -237,9 +609,10 @@ impl AsRawFd for VsockStream {
 
 impl IntoRawFd for VsockStream {
     fn into_raw_fd(self) -> RawFd {
-        let fd = self.inner.get_ref().as_raw_fd();
-        mem::forget(self);
-        fd
+        // Release the accept filter slot and timers along with the stream,
+        // only the socket itself is handed out. Zero-copy buffers the kernel
+        // still uses are leaked, see `send_zerocopy`.
+        OwnedFd::from(self).into_raw_fd()
     }
 }
 
@@ -276,13 +649,12 @@ impl AsyncWrite for VsockStream {
         self.poll_write_priv(cx, buf)
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
-        Poll::Ready(Ok(()))
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_flush_priv(cx)
     }
 
-    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<()>> {
-        self.shutdown(std::net::Shutdown::Write)?;
-        Poll::Ready(Ok(()))
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.poll_shutdown_priv(cx)
     }
 }
 
@@ -0,0 +1,191 @@
+//! The socket and pipe calls behind the streams, listeners and transfers,
+//! shared by the Tokio and async-io based types.
+
+use std::io::{Error, Result};
+use std::mem::size_of;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+
+use libc::*;
+
+use crate::flags::{bind_listener, map_flags_error, peer_flags, sockaddr_vm, VsockFlags};
+use crate::{VsockAddr, VsockConnectInfo};
+
+/// Create a listening socket bound to `addr`.
+pub(crate) fn bind(addr: VsockAddr, flags: VsockFlags) -> Result<vsock::VsockListener> {
+    if flags.is_empty() {
+        vsock::VsockListener::bind_with_cid_port(addr.cid(), addr.port())
+    } else {
+        bind_listener(addr, flags)
+    }
+}
+
+/// Create a non-blocking socket and start connecting it to `addr`.
+///
+/// The connection is established once the socket becomes writable, see
+/// [`take_error`].
+pub(crate) fn start_connect(addr: VsockAddr, flags: VsockFlags) -> Result<vsock::VsockStream> {
+    let raw_addr = sockaddr_vm(addr, flags)?;
+    let fd = unsafe { socket(AF_VSOCK, SOCK_STREAM, 0) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    // Safety: the socket was just created and is owned by nobody else, so it
+    // is closed if anything below fails.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    if unsafe { fcntl(fd.as_raw_fd(), F_SETFL, O_NONBLOCK) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    if unsafe { fcntl(fd.as_raw_fd(), F_SETFD, FD_CLOEXEC) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    if unsafe {
+        connect(
+            fd.as_raw_fd(),
+            &raw_addr as *const _ as *const sockaddr,
+            size_of::<sockaddr_vm>() as socklen_t,
+        )
+    } < 0
+    {
+        let err = Error::last_os_error();
+        // Connect hasn't finished, that's fine.
+        if err.raw_os_error() != Some(EINPROGRESS) {
+            return Err(map_flags_error(err, flags));
+        }
+    }
+    Ok(vsock::VsockStream::from(fd))
+}
+
+/// Take the pending error of `fd`, which tells whether a connection attempt
+/// failed once the socket became writable.
+pub(crate) fn take_error(fd: RawFd) -> Result<()> {
+    let mut sock_err: c_int = 0;
+    let mut sock_err_len: socklen_t = size_of::<c_int>() as socklen_t;
+    let err = unsafe {
+        getsockopt(
+            fd,
+            SOL_SOCKET,
+            SO_ERROR,
+            &mut sock_err as *mut _ as *mut c_void,
+            &mut sock_err_len as *mut socklen_t,
+        )
+    };
+    if err < 0 {
+        return Err(Error::last_os_error());
+    }
+    if sock_err == 0 {
+        Ok(())
+    } else {
+        Err(Error::from_raw_os_error(sock_err))
+    }
+}
+
+/// The connection info of a connected socket.
+pub(crate) fn connect_info(stream: &vsock::VsockStream) -> VsockConnectInfo {
+    VsockConnectInfo::new(
+        stream.local_addr().ok(),
+        stream.peer_addr().ok(),
+        peer_flags(stream.as_raw_fd()).unwrap_or_default(),
+    )
+}
+
+/// The size of a pipe buffer, and the most moved through it at once.
+pub(crate) const PIPE_SIZE: usize = 64 * 1024;
+
+/// Create a pipe with `flags` in addition to `O_CLOEXEC`, returning its read
+/// and write ends.
+pub(crate) fn pipe(flags: c_int) -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { pipe2(fds.as_mut_ptr(), O_CLOEXEC | flags) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    // Safety: both ends were just created and are owned by nobody else.
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// `duration` in whole seconds for `SO_LINGER`, rounded up so that a short
+/// linger does not turn into `l_linger == 0`, which resets the connection on
+/// close.
+fn linger_secs(duration: Duration) -> c_int {
+    let secs = duration.as_secs() + (duration.subsec_nanos() > 0) as u64;
+    secs.min(c_int::MAX as u64) as c_int
+}
+
+/// Set the `SO_LINGER` option of `fd`.
+pub(crate) fn set_linger(fd: RawFd, duration: Option<Duration>) -> Result<()> {
+    let value = linger {
+        l_onoff: duration.is_some() as c_int,
+        l_linger: duration.map_or(0, linger_secs),
+    };
+    let ret = unsafe {
+        setsockopt(
+            fd,
+            SOL_SOCKET,
+            SO_LINGER,
+            &value as *const _ as *const c_void,
+            size_of::<linger>() as socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The `SO_LINGER` option of `fd`.
+pub(crate) fn linger(fd: RawFd) -> Result<Option<Duration>> {
+    let mut value = linger {
+        l_onoff: 0,
+        l_linger: 0,
+    };
+    let mut len = size_of::<linger>() as socklen_t;
+    let ret = unsafe {
+        getsockopt(
+            fd,
+            SOL_SOCKET,
+            SO_LINGER,
+            &mut value as *mut _ as *mut c_void,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok((value.l_onoff != 0).then(|| Duration::from_secs(value.l_linger as u64)))
+}
+
+/// The number of bytes in the send queue of `fd`.
+pub(crate) fn unsent_bytes(fd: RawFd) -> Result<usize> {
+    queue_len(fd, TIOCOUTQ)
+}
+
+/// The number of bytes in the receive queue of `fd`.
+pub(crate) fn unread_bytes(fd: RawFd) -> Result<usize> {
+    queue_len(fd, FIONREAD)
+}
+
+fn queue_len(fd: RawFd, request: Ioctl) -> Result<usize> {
+    let mut len: c_int = 0;
+    if unsafe { ioctl(fd, request, &mut len) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(len as usize)
+}
+
+/// Whether the peer of `fd` closed the connection or shut down its writing
+/// half.
+pub(crate) fn is_read_closed(fd: RawFd) -> Result<bool> {
+    let mut fd = pollfd {
+        fd,
+        events: POLLRDHUP,
+        revents: 0,
+    };
+    if unsafe { poll(&mut fd, 1, 0) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(fd.revents & (POLLRDHUP | POLLHUP | POLLERR) != 0)
+}
@@ -0,0 +1,170 @@
+//! Read, write and idle timeouts of streams, and the activity they are
+//! measured against.
+
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::time::{Instant, Sleep};
+
+/// The timeouts of a stream, one timer per direction so that the halves of a
+/// split stream are woken independently.
+#[derive(Debug, Default)]
+pub(crate) struct Timeouts {
+    read: Mutex<Timer>,
+    write: Mutex<Timer>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Timer {
+    timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    /// Armed while an operation is pending, and restarted whenever it is
+    /// polled before the deadline.
+    pending: Option<Pin<Box<Sleep>>>,
+    idle: Option<Pin<Box<Sleep>>>,
+}
+
+/// The timeout that failed an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Expired {
+    Operation,
+    Idle,
+}
+
+impl From<Expired> for Error {
+    fn from(expired: Expired) -> Self {
+        let msg = match expired {
+            Expired::Operation => "operation timed out",
+            Expired::Idle => "connection was idle for too long",
+        };
+        Error::new(ErrorKind::TimedOut, msg)
+    }
+}
+
+fn lock(timer: &Mutex<Timer>) -> MutexGuard<'_, Timer> {
+    timer
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Timeouts {
+    pub(crate) fn read(&self) -> MutexGuard<'_, Timer> {
+        lock(&self.read)
+    }
+
+    pub(crate) fn write(&self) -> MutexGuard<'_, Timer> {
+        lock(&self.write)
+    }
+
+    pub(crate) fn idle_timeout(&self) -> Option<Duration> {
+        self.read().idle_timeout
+    }
+
+    pub(crate) fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        for mut timer in [self.read(), self.write()] {
+            timer.idle_timeout = timeout;
+            timer.idle = None;
+        }
+    }
+}
+
+impl Timer {
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        self.pending = None;
+    }
+
+    /// Disarm the timer once an operation made progress.
+    pub(crate) fn done(&mut self) {
+        self.pending = None;
+    }
+
+    /// Fail a pending operation once it made no progress for the timeout, or
+    /// the stream was idle for the idle timeout.
+    pub(crate) fn poll_expired(
+        &mut self,
+        cx: &mut Context<'_>,
+        activity: Option<&Activity>,
+    ) -> Poll<Expired> {
+        if let Some(timeout) = self.timeout {
+            let now = Instant::now();
+            let sleep = self
+                .pending
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(now + timeout)));
+            if sleep.deadline() <= now {
+                self.pending = None;
+                return Poll::Ready(Expired::Operation);
+            }
+            // A poll before the deadline may come from a new operation, after
+            // the waiting one was dropped, which must not inherit its deadline.
+            // A waiting operation is only polled again once woken, which
+            // leaves the deadline in place unless something else woke its task.
+            sleep.as_mut().reset(now + timeout);
+            let _ = sleep.as_mut().poll(cx);
+        }
+        if let (Some(timeout), Some(activity)) = (self.idle_timeout, activity) {
+            let deadline = activity.last() + timeout;
+            let sleep = match &mut self.idle {
+                Some(sleep) => {
+                    if sleep.deadline() != deadline {
+                        sleep.as_mut().reset(deadline);
+                    }
+                    sleep
+                }
+                None => self
+                    .idle
+                    .insert(Box::pin(tokio::time::sleep_until(deadline))),
+            };
+            if sleep.as_mut().poll(cx).is_ready() {
+                self.idle = None;
+                return Poll::Ready(Expired::Idle);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// The time a stream last read or wrote something.
+#[derive(Debug)]
+pub(crate) struct Activity {
+    start: Instant,
+    last: AtomicU64,
+}
+
+impl Activity {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn touch(&self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        self.last.store(elapsed, Ordering::Relaxed);
+    }
+
+    pub(crate) fn last(&self) -> Instant {
+        self.start + Duration::from_millis(self.last.load(Ordering::Relaxed))
+    }
+
+    /// Complete once the stream was idle for `timeout`.
+    pub(crate) async fn expired(self: Arc<Self>, timeout: Duration) {
+        loop {
+            let deadline = self.last() + timeout;
+            if deadline <= Instant::now() {
+                return;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
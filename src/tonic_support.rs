@@ -1,19 +1,167 @@
-use crate::VsockAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use crate::{VsockAddr, VsockFlags, VMADDR_CID_HOST, VMADDR_CID_LOCAL};
+
+/// Source of [`VsockConnectInfo::connection_id`].
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The transport carrying a Virtio socket connection.
+///
+/// This is derived the way Linux assigns transports to connections: from the
+/// peer's CID and [`VsockFlags`], and the CID of this machine.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum VsockTransport {
+    /// Both ends live on the same machine (`VMADDR_CID_LOCAL`, or the CID of
+    /// this machine).
+    Loopback,
+    /// A connection through the transport towards this machine's host or
+    /// hypervisor, including connections to siblings sent with
+    /// [`VsockFlags::TO_HOST`].
+    GuestToHost,
+    /// A connection through the transport towards one of this machine's own
+    /// guests, including those of a guest running nested guests.
+    HostToGuest,
+    /// The peer address is unknown.
+    Unknown,
+}
+
+impl VsockTransport {
+    /// The transport Linux picks for a connection with `peer`, see
+    /// `vsock_assign_transport`.
+    fn for_peer(peer: Option<VsockAddr>, peer_flags: VsockFlags) -> Self {
+        match peer.map(|addr| addr.cid()) {
+            Some(VMADDR_CID_LOCAL) => VsockTransport::Loopback,
+            Some(cid) if Some(cid) == local_cid() => VsockTransport::Loopback,
+            Some(cid) if cid <= VMADDR_CID_HOST || peer_flags.contains(VsockFlags::TO_HOST) => {
+                VsockTransport::GuestToHost
+            }
+            Some(_) => VsockTransport::HostToGuest,
+            None => VsockTransport::Unknown,
+        }
+    }
+}
+
+/// The CID of this machine, looked up once with
+/// `IOCTL_VM_SOCKETS_GET_LOCAL_CID`.
+fn local_cid() -> Option<u32> {
+    static LOCAL_CID: OnceLock<Option<u32>> = OnceLock::new();
+    *LOCAL_CID.get_or_init(|| vsock::get_local_cid().ok())
+}
 
 /// Connection info for a Vsock Stream.
 ///
+/// The info is captured once, when the connection is accepted or established,
+/// and stays the same for the lifetime of the stream.
+///
 /// See [`Connected`][tonic012::transport::server::Connected] for more details.
 ///
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        from = "crate::serde_support::ConnectInfoRepr",
+        into = "crate::serde_support::ConnectInfoRepr"
+    )
+)]
 pub struct VsockConnectInfo {
     peer_addr: Option<VsockAddr>,
+    peer_flags: VsockFlags,
+    local_addr: Option<VsockAddr>,
+    connected_at: SystemTime,
+    connection_id: u64,
+    transport: VsockTransport,
 }
 
 impl VsockConnectInfo {
+    pub(crate) fn new(
+        local_addr: Option<VsockAddr>,
+        peer_addr: Option<VsockAddr>,
+        peer_flags: VsockFlags,
+    ) -> Self {
+        VsockConnectInfo {
+            peer_addr,
+            peer_flags,
+            local_addr,
+            connected_at: SystemTime::now(),
+            connection_id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            transport: VsockTransport::for_peer(peer_addr, peer_flags),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_parts(
+        local_addr: Option<VsockAddr>,
+        peer_addr: Option<VsockAddr>,
+        peer_flags: VsockFlags,
+        connected_at: SystemTime,
+        connection_id: u64,
+        transport: VsockTransport,
+    ) -> Self {
+        VsockConnectInfo {
+            peer_addr,
+            peer_flags,
+            local_addr,
+            connected_at,
+            connection_id,
+            transport,
+        }
+    }
+
+    /// Record the addresses of a connection that finished connecting after
+    /// this info was captured.
+    pub(crate) fn set_addrs(
+        &mut self,
+        local_addr: Option<VsockAddr>,
+        peer_addr: Option<VsockAddr>,
+        peer_flags: VsockFlags,
+    ) {
+        self.local_addr = local_addr;
+        self.peer_addr = peer_addr;
+        self.peer_flags = peer_flags;
+        self.transport = VsockTransport::for_peer(peer_addr, peer_flags);
+    }
+
     /// Return the remote address the IO resource is connected too.
     pub fn peer_addr(&self) -> Option<VsockAddr> {
         self.peer_addr
     }
+
+    /// Return the flags of the remote address, e.g.
+    /// [`VsockFlags::TO_HOST`] for connections forwarded through the host.
+    pub fn peer_flags(&self) -> VsockFlags {
+        self.peer_flags
+    }
+
+    /// Return the local address the connection arrived on.
+    ///
+    /// For a listener bound to several ports, the port tells them apart.
+    pub fn local_addr(&self) -> Option<VsockAddr> {
+        self.local_addr
+    }
+
+    /// Return when the connection was accepted or established.
+    pub fn connected_at(&self) -> SystemTime {
+        self.connected_at
+    }
+
+    /// Return an identifier for the connection, unique within this process and
+    /// increasing in the order the connections were made.
+    pub fn connection_id(&self) -> u64 {
+        self.connection_id
+    }
+
+    /// Return the transport carrying the connection.
+    pub fn transport(&self) -> VsockTransport {
+        self.transport
+    }
 }
 
 macro_rules! tonic_connected {
@@ -26,9 +174,31 @@ macro_rules! tonic_connected {
             type ConnectInfo = VsockConnectInfo;
 
             fn connect_info(&self) -> Self::ConnectInfo {
-                VsockConnectInfo {
-                    peer_addr: self.peer_addr().ok(),
-                }
+                self.connect_info().clone()
+            }
+        }
+
+        /// Allow tonic to serve connections yielded by [`VsockIncoming`](crate::VsockIncoming).
+        ///
+        #[cfg(feature = $cfg)]
+        #[cfg_attr(docsrs, doc(cfg(feature = $cfg)))]
+        impl $tonic_version::transport::server::Connected for crate::VsockIncomingStream {
+            type ConnectInfo = VsockConnectInfo;
+
+            fn connect_info(&self) -> Self::ConnectInfo {
+                self.get_ref().connect_info().clone()
+            }
+        }
+
+        /// Allow tonic to serve connections accepted by an [`EitherListener`](crate::EitherListener).
+        ///
+        #[cfg(feature = $cfg)]
+        #[cfg_attr(docsrs, doc(cfg(feature = $cfg)))]
+        impl $tonic_version::transport::server::Connected for crate::EitherStream {
+            type ConnectInfo = crate::EitherConnectInfo;
+
+            fn connect_info(&self) -> Self::ConnectInfo {
+                self.connect_info()
             }
         }
     };
@@ -0,0 +1,171 @@
+#![cfg(feature = "tower")]
+
+use std::task::{Context, Poll};
+
+use futures::future::{ready, Either, Ready};
+use http1::{header, HeaderValue, Request, Response, StatusCode};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{EitherConnectInfo, SharedPeerPolicy, VsockAddr, VsockConnectInfo};
+
+/// How a [`PeerPolicyService`] rejects requests from peers that are not allowed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Rejection {
+    /// Respond with `403 Forbidden`.
+    Http,
+    /// Respond with the gRPC status `PERMISSION_DENIED`.
+    Grpc,
+}
+
+/// A [`Layer`] enforcing a [`PeerPolicy`](crate::PeerPolicy) on requests
+/// served over Virtio sockets.
+///
+/// The peer address is read from the request extensions, where tonic stores the
+/// [`VsockConnectInfo`] (or [`EitherConnectInfo`]) of the connection, and axum
+/// stores the `ConnectInfo` requested with `into_make_service_with_connect_info`.
+/// Requests without a vsock peer address, e.g. those received over TCP through
+/// an [`EitherListener`](crate::EitherListener), are rejected unless
+/// [`allow_unidentified`](PeerPolicyLayer::allow_unidentified) is set.
+#[derive(Debug, Clone)]
+pub struct PeerPolicyLayer {
+    policy: SharedPeerPolicy,
+    rejection: Rejection,
+    allow_unidentified: bool,
+}
+
+impl PeerPolicyLayer {
+    /// Create a layer that rejects requests with `403 Forbidden`, e.g. for axum.
+    pub fn http(policy: impl Into<SharedPeerPolicy>) -> Self {
+        Self::new(policy.into(), Rejection::Http)
+    }
+
+    /// Create a layer that rejects requests with the gRPC status
+    /// `PERMISSION_DENIED`, e.g. for tonic.
+    pub fn grpc(policy: impl Into<SharedPeerPolicy>) -> Self {
+        Self::new(policy.into(), Rejection::Grpc)
+    }
+
+    fn new(policy: SharedPeerPolicy, rejection: Rejection) -> Self {
+        Self {
+            policy,
+            rejection,
+            allow_unidentified: false,
+        }
+    }
+
+    /// Let requests through that do not carry a vsock peer address.
+    pub fn allow_unidentified(mut self, allow: bool) -> Self {
+        self.allow_unidentified = allow;
+        self
+    }
+}
+
+impl<S> Layer<S> for PeerPolicyLayer {
+    type Service = PeerPolicyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PeerPolicyService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`PeerPolicyLayer`].
+#[derive(Debug, Clone)]
+pub struct PeerPolicyService<S> {
+    inner: S,
+    layer: PeerPolicyLayer,
+}
+
+impl<S> PeerPolicyService<S> {
+    fn is_allowed(&self, extensions: &http1::Extensions) -> bool {
+        match peer_addr(extensions) {
+            Some(addr) => self.layer.policy.is_allowed(&addr),
+            None => self.layer.allow_unidentified,
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for PeerPolicyService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<S::Future, Ready<Result<S::Response, S::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if self.is_allowed(req.extensions()) {
+            Either::Left(self.inner.call(req))
+        } else {
+            Either::Right(ready(Ok(reject(self.layer.rejection))))
+        }
+    }
+}
+
+fn reject<B: Default>(rejection: Rejection) -> Response<B> {
+    let mut response = Response::new(B::default());
+    match rejection {
+        Rejection::Http => *response.status_mut() = StatusCode::FORBIDDEN,
+        Rejection::Grpc => {
+            // gRPC reports errors with a trailers-only response, which is
+            // sent as a plain set of headers.
+            let headers = response.headers_mut();
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/grpc"),
+            );
+            headers.insert("grpc-status", HeaderValue::from_static("7"));
+            headers.insert(
+                "grpc-message",
+                HeaderValue::from_static("peer%20not%20allowed"),
+            );
+        }
+    }
+    response
+}
+
+fn peer_addr(extensions: &http1::Extensions) -> Option<VsockAddr> {
+    if let Some(info) = extensions.get::<VsockConnectInfo>() {
+        return info.peer_addr();
+    }
+    if let Some(info) = extensions.get::<EitherConnectInfo>() {
+        return either_peer_addr(info);
+    }
+
+    #[cfg(feature = "axum08")]
+    {
+        use axum08::extract::ConnectInfo;
+
+        if let Some(ConnectInfo(info)) = extensions.get::<ConnectInfo<VsockConnectInfo>>() {
+            return info.peer_addr();
+        }
+        if let Some(ConnectInfo(info)) = extensions.get::<ConnectInfo<EitherConnectInfo>>() {
+            return either_peer_addr(info);
+        }
+        if let Some(ConnectInfo(addr)) = extensions.get::<ConnectInfo<VsockAddr>>() {
+            return Some(*addr);
+        }
+        if let Some(ConnectInfo(crate::EitherAddr::Vsock(addr))) =
+            extensions.get::<ConnectInfo<crate::EitherAddr>>()
+        {
+            return Some(*addr);
+        }
+    }
+
+    None
+}
+
+fn either_peer_addr(info: &EitherConnectInfo) -> Option<VsockAddr> {
+    match info {
+        EitherConnectInfo::Vsock(info) => info.peer_addr(),
+        _ => None,
+    }
+}
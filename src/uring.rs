@@ -0,0 +1,596 @@
+//! Virtio sockets for the completion based [`tokio-uring`] runtime.
+//!
+//! Accepting, connecting, reading, writing and zero-copy sends are submitted
+//! to io_uring, and take ownership of their buffers until they complete, like
+//! the other `tokio-uring` types. Reads and writes go through tokio-uring's own
+//! ring. tokio-uring 0.4 has no accept and connect operations for Virtio
+//! sockets and no `IORING_OP_SEND_ZC`, so those are submitted to a second ring
+//! per thread, whose completions are reaped by a task on the same runtime.
+//!
+//! Addresses, flags and connection info are shared with the epoll based types:
+//!
+//! ```no_run
+//! use tokio_uring::buf::IoBuf;
+//! use tokio_vsock::uring::VsockListener;
+//! use tokio_vsock::VMADDR_CID_ANY;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! tokio_uring::start(async {
+//!     let listener = VsockListener::bind((VMADDR_CID_ANY, 8000))?;
+//!     loop {
+//!         let (stream, _addr) = listener.accept().await?;
+//!         tokio_uring::spawn(async move {
+//!             let mut buf = vec![0u8; 4096];
+//!             loop {
+//!                 let (read, filled) = stream.read(buf).await;
+//!                 match read {
+//!                     Ok(0) | Err(_) => break,
+//!                     Ok(len) => {
+//!                         let (written, slice) = stream.write_all(filled.slice(..len)).await;
+//!                         buf = slice.into_inner();
+//!                         if written.is_err() {
+//!                             break;
+//!                         }
+//!                     }
+//!                 }
+//!             }
+//!         });
+//!     }
+//! })
+//! # }
+//! ```
+//!
+//! [`tokio-uring`]: https://docs.rs/tokio-uring
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind, Result};
+use std::mem::{self, size_of};
+use std::net::Shutdown;
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::rc::{Rc, Weak};
+use std::task::{Poll, Waker};
+
+use futures::future::poll_fn;
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+use libc::*;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio_uring::buf::{IoBuf, IoBufMut};
+use tokio_uring::BufResult;
+
+use crate::addr::each_addr;
+use crate::filter::ConnectionSlot;
+use crate::flags::{map_flags_error, sockaddr_vm};
+use crate::sys;
+use crate::{AcceptFilter, ToVsockAddrs, VsockAddr, VsockConnectInfo, VsockFlags};
+
+/// A Virtio socket connected to a remote endpoint, driven by io_uring.
+pub struct VsockStream {
+    // The operations of tokio-uring's Unix stream do not depend on the
+    // address family of the socket.
+    inner: tokio_uring::net::UnixStream,
+    info: VsockConnectInfo,
+    /// Counts the connection against the accept filter of its listener.
+    _slot: Option<ConnectionSlot>,
+}
+
+impl VsockStream {
+    /// Open a connection to a remote host, see
+    /// [`VsockStream::connect`](crate::VsockStream::connect).
+    pub async fn connect<A: ToVsockAddrs>(addr: A) -> Result<Self> {
+        Self::connect_with_flags(addr, VsockFlags::empty()).await
+    }
+
+    /// Open a connection to a remote host, setting `flags` in the destination
+    /// address, see
+    /// [`VsockStream::connect_with_flags`](crate::VsockStream::connect_with_flags).
+    pub async fn connect_with_flags<A: ToVsockAddrs>(addr: A, flags: VsockFlags) -> Result<Self> {
+        let mut last_err = None;
+        for addr in crate::addr::lookup(addr).await? {
+            match Self::connect_addr(addr, flags).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(crate::addr::no_addresses))
+    }
+
+    async fn connect_addr(addr: VsockAddr, flags: VsockFlags) -> Result<Self> {
+        let raw_addr = Box::new(sockaddr_vm(addr, flags)?);
+        let fd = unsafe { socket(AF_VSOCK, SOCK_STREAM | SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        // Safety: the socket was just created and is owned by nobody else.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let entry = opcode::Connect::new(
+            types::Fd(fd.as_raw_fd()),
+            &*raw_addr as *const _ as *const sockaddr,
+            size_of::<libc::sockaddr_vm>() as socklen_t,
+        )
+        .build();
+        // Safety: the address is owned by the operation.
+        let op = unsafe { Ring::current()?.submit(entry, raw_addr) }.map_err(|(err, _)| err)?;
+        let ret = op.next().await.result();
+        if ret < 0 {
+            return Err(map_flags_error(Error::from_raw_os_error(-ret), flags));
+        }
+        let stream = vsock::VsockStream::from(fd);
+        let info = sys::connect_info(&stream);
+        Ok(Self::from_fd(OwnedFd::from(stream), info, None))
+    }
+
+    /// Hand a connected epoll based stream over to io_uring.
+    pub fn from_stream(mut stream: crate::VsockStream) -> Result<Self> {
+        let info = stream.connect_info().clone();
+        let slot = stream.take_slot();
+        let fd = OwnedFd::from(stream);
+        set_blocking(&fd)?;
+        Ok(Self::from_fd(fd, info, slot))
+    }
+
+    /// Wrap a connected, blocking socket.
+    fn from_fd(fd: OwnedFd, info: VsockConnectInfo, slot: Option<ConnectionSlot>) -> Self {
+        // Safety: the socket is owned by the io_uring stream from now on.
+        let inner = unsafe { tokio_uring::net::UnixStream::from_raw_fd(fd.into_raw_fd()) };
+        Self {
+            inner,
+            info,
+            _slot: slot,
+        }
+    }
+
+    /// The local address that this socket is bound to.
+    pub fn local_addr(&self) -> Result<VsockAddr> {
+        self.info
+            .local_addr()
+            .ok_or_else(|| ErrorKind::NotConnected.into())
+    }
+
+    /// The remote address that this socket is connected to.
+    pub fn peer_addr(&self) -> Result<VsockAddr> {
+        self.info
+            .peer_addr()
+            .ok_or_else(|| ErrorKind::NotConnected.into())
+    }
+
+    /// The connection info captured when this connection was accepted or
+    /// established.
+    pub fn connect_info(&self) -> &VsockConnectInfo {
+        &self.info
+    }
+
+    /// Read into `buf`, returning the number of bytes read and the buffer.
+    pub async fn read<T: IoBufMut>(&self, buf: T) -> BufResult<usize, T> {
+        self.inner.read(buf).await
+    }
+
+    /// Write from `buf`, returning the number of bytes written and the buffer.
+    pub async fn write<T: IoBuf>(&self, buf: T) -> BufResult<usize, T> {
+        self.inner.write(buf).await
+    }
+
+    /// Write all of `buf`, resubmitting after partial writes.
+    pub async fn write_all<T: IoBuf>(&self, buf: T) -> BufResult<(), T> {
+        self.inner.write_all(buf).await
+    }
+
+    /// Write from several buffers at once.
+    pub async fn writev<T: IoBuf>(&self, bufs: Vec<T>) -> BufResult<usize, Vec<T>> {
+        self.inner.writev(bufs).await
+    }
+
+    /// Send from `buf` without copying it into the kernel, using
+    /// `IORING_OP_SEND_ZC`, and return the buffer once the kernel no longer
+    /// uses it.
+    ///
+    /// Virtio sockets support zero-copy sends since Linux 6.7. On older
+    /// kernels this falls back to a regular write. If the future is dropped
+    /// before the kernel released the buffer, the buffer is kept until then.
+    pub async fn send_zc<T: IoBuf>(&self, buf: T) -> BufResult<usize, T> {
+        let len = buf.bytes_init().min(u32::MAX as usize) as u32;
+        let entry = opcode::SendZc::new(types::Fd(self.as_raw_fd()), buf.stable_ptr(), len).build();
+        let ring = match Ring::current() {
+            Ok(ring) => ring,
+            Err(err) => return (Err(err), buf),
+        };
+        // Safety: the buffer is owned by the operation, and its memory does
+        // not move along with it.
+        let op = match unsafe { ring.submit(entry, buf) } {
+            Ok(op) => op,
+            Err((err, buf)) => return (Err(err), buf),
+        };
+
+        let sent = op.next().await;
+        if cqueue::more(sent.flags()) {
+            // The kernel releases the buffer with a notification.
+            op.next().await;
+        }
+        let buf = op.into_data::<T>();
+        match sent.result() {
+            ret if ret >= 0 => (Ok(ret as usize), buf),
+            // The socket or the kernel does not support zero-copy sends.
+            ret if -ret == EOPNOTSUPP || -ret == EINVAL => self.inner.write(buf).await,
+            ret => (Err(Error::from_raw_os_error(-ret)), buf),
+        }
+    }
+
+    /// Shuts down the read, write, or both halves of this connection.
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.inner.shutdown(how)
+    }
+}
+
+impl AsRawFd for VsockStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl std::fmt::Debug for VsockStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("uring::VsockStream")
+            .field("fd", &self.as_raw_fd())
+            .field("info", &self.info)
+            .finish()
+    }
+}
+
+/// A Virtio socket server, listening for connections handed to io_uring.
+#[derive(Debug)]
+pub struct VsockListener {
+    inner: vsock::VsockListener,
+    filter: Option<AcceptFilter>,
+}
+
+impl VsockListener {
+    /// Create a new Virtio socket listener bound to `addr`, see
+    /// [`VsockListener::bind`](crate::VsockListener::bind).
+    pub fn bind<A: ToVsockAddrs>(addr: A) -> Result<Self> {
+        Self::bind_with_flags(addr, VsockFlags::empty())
+    }
+
+    /// Create a new Virtio socket listener bound to `addr` with `flags`, see
+    /// [`VsockListener::bind_with_flags`](crate::VsockListener::bind_with_flags).
+    pub fn bind_with_flags<A: ToVsockAddrs>(addr: A, flags: VsockFlags) -> Result<Self> {
+        each_addr(addr, |addr| {
+            Ok(Self {
+                inner: sys::bind(addr, flags)?,
+                filter: None,
+            })
+        })
+    }
+
+    /// Accept a new incoming connection.
+    ///
+    /// Connections rejected by the [`AcceptFilter`] of this listener are closed
+    /// right away and never returned.
+    pub async fn accept(&self) -> Result<(VsockStream, VsockAddr)> {
+        loop {
+            let raw_addr = Box::new((
+                unsafe { mem::zeroed::<libc::sockaddr_vm>() },
+                size_of::<libc::sockaddr_vm>() as socklen_t,
+            ));
+            let entry = opcode::Accept::new(
+                types::Fd(self.as_raw_fd()),
+                &raw_addr.0 as *const _ as *mut sockaddr,
+                &raw_addr.1 as *const _ as *mut socklen_t,
+            )
+            .flags(SOCK_CLOEXEC)
+            .build();
+            // Safety: the address is owned by the operation.
+            let op = unsafe { Ring::current()?.submit(entry, raw_addr) }.map_err(|(err, _)| err)?;
+            let ret = op.next().await.result();
+            if ret < 0 {
+                return Err(Error::from_raw_os_error(-ret));
+            }
+            // Safety: the kernel just created the socket for this accept.
+            let stream = vsock::VsockStream::from(unsafe { OwnedFd::from_raw_fd(ret) });
+            let raw_addr = op.into_data::<Box<(libc::sockaddr_vm, socklen_t)>>();
+            let addr = VsockAddr::new(raw_addr.0.svm_cid, raw_addr.0.svm_port);
+
+            let slot = match &self.filter {
+                Some(filter) => match filter.admit(&addr) {
+                    Some(slot) => Some(slot),
+                    // Dropping the socket closes the connection.
+                    None => continue,
+                },
+                None => None,
+            };
+            let info = sys::connect_info(&stream);
+            return Ok((
+                VsockStream::from_fd(OwnedFd::from(stream), info, slot),
+                addr,
+            ));
+        }
+    }
+
+    /// The local address that this listener is bound to.
+    pub fn local_addr(&self) -> Result<VsockAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Restrict the connections this listener accepts.
+    ///
+    /// See [`AcceptFilter`] for details.
+    pub fn set_accept_filter(&mut self, filter: AcceptFilter) {
+        self.filter = Some(filter);
+    }
+
+    /// The accept filter of this listener, if any.
+    pub fn accept_filter(&self) -> Option<&AcceptFilter> {
+        self.filter.as_ref()
+    }
+}
+
+impl AsRawFd for VsockListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl From<crate::VsockListener> for VsockListener {
+    fn from(listener: crate::VsockListener) -> Self {
+        let filter = listener.accept_filter().cloned();
+        let fd = OwnedFd::from(listener);
+        set_blocking(&fd).expect("unable to clear O_NONBLOCK");
+        Self {
+            inner: vsock::VsockListener::from(fd),
+            filter,
+        }
+    }
+}
+
+/// Clear `O_NONBLOCK`, since io_uring fails operations on non-blocking sockets
+/// that are not ready, instead of waiting for them.
+fn set_blocking(fd: &OwnedFd) -> Result<()> {
+    let flags = unsafe { fcntl(fd.as_raw_fd(), F_GETFL) };
+    if flags < 0 || unsafe { fcntl(fd.as_raw_fd(), F_SETFL, flags & !O_NONBLOCK) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The `user_data` of submissions whose completions nobody waits for.
+const IGNORED: u64 = u64::MAX;
+
+/// The ring for the operations tokio-uring does not provide, one per thread
+/// like tokio-uring's own.
+struct Ring {
+    /// Readiness of the ring once the kernel posted completions, dropped
+    /// before the ring is closed.
+    readiness: AsyncFd<RawFd>,
+    ring: RefCell<IoUring>,
+    ops: RefCell<HashMap<u64, Operation>>,
+    next_id: Cell<u64>,
+    /// Whether submissions are queued that the kernel did not take yet.
+    unsubmitted: Cell<bool>,
+}
+
+/// An operation in flight on a [`Ring`].
+struct Operation {
+    completions: VecDeque<cqueue::Entry>,
+    waker: Option<Waker>,
+    /// Whether the kernel posted the last completion of the operation.
+    finished: bool,
+    /// Whether the [`Op`] waiting for the operation was dropped.
+    abandoned: bool,
+    /// Memory the kernel uses until the operation finished.
+    data: Option<Box<dyn Any>>,
+}
+
+thread_local! {
+    static RING: RefCell<Weak<Ring>> = const { RefCell::new(Weak::new()) };
+}
+
+impl Ring {
+    /// The ring of this thread, created along with the task reaping its
+    /// completions once the first operation is submitted.
+    ///
+    /// Panics outside of the tokio-uring runtime, like tokio-uring's own
+    /// operations.
+    fn current() -> Result<Rc<Ring>> {
+        RING.with(|current| {
+            if let Some(ring) = current.borrow().upgrade() {
+                return Ok(ring);
+            }
+            let ring = IoUring::new(64)?;
+            // Safety: the ring outlives its registration, see `Ring::readiness`.
+            let readiness =
+                unsafe { AsyncFd::register_with_interest(ring.as_raw_fd(), Interest::READABLE) }?;
+            let ring = Rc::new(Ring {
+                readiness,
+                ring: RefCell::new(ring),
+                ops: RefCell::new(HashMap::new()),
+                next_id: Cell::new(0),
+                unsubmitted: Cell::new(false),
+            });
+            // The task keeps the ring alive until the runtime shuts down.
+            tokio_uring::spawn(ring.clone().reap());
+            *current.borrow_mut() = Rc::downgrade(&ring);
+            Ok(ring)
+        })
+    }
+
+    /// Submit `entry`, which the kernel completes into an [`Op`], keeping
+    /// `data` until the operation finished.
+    ///
+    /// # Safety
+    ///
+    /// All memory `entry` refers to must be owned by `data` and must not move
+    /// along with it.
+    unsafe fn submit<T: 'static>(
+        self: &Rc<Self>,
+        entry: squeue::Entry,
+        data: T,
+    ) -> std::result::Result<Op, (Error, T)> {
+        let id = self.next_id.get();
+        let entry = entry.user_data(id);
+        {
+            let mut ring = self.ring.borrow_mut();
+            if ring.submission().push(&entry).is_err() {
+                // The submission queue is full.
+                if let Err(err) = ring.submit() {
+                    return Err((err, data));
+                }
+                if ring.submission().push(&entry).is_err() {
+                    return Err((ErrorKind::WouldBlock.into(), data));
+                }
+            }
+        }
+        self.next_id.set(id.wrapping_add(1) % IGNORED);
+        self.ops.borrow_mut().insert(
+            id,
+            Operation {
+                completions: VecDeque::new(),
+                waker: None,
+                finished: false,
+                abandoned: false,
+                data: Some(Box::new(data)),
+            },
+        );
+        // The entry is queued, so a failed submission is retried while
+        // waiting for the operation.
+        self.unsubmitted.set(true);
+        self.flush();
+        Ok(Op {
+            ring: self.clone(),
+            id,
+        })
+    }
+
+    /// Hand the queued submissions to the kernel.
+    fn flush(&self) -> bool {
+        if self.unsubmitted.get() && self.ring.borrow().submit().is_ok() {
+            self.unsubmitted.set(false);
+        }
+        !self.unsubmitted.get()
+    }
+
+    /// Hand the completions posted by the kernel to their operations, until
+    /// the runtime shuts down.
+    async fn reap(self: Rc<Self>) {
+        while let Ok(mut guard) = self.readiness.readable().await {
+            self.complete();
+            guard.clear_ready();
+        }
+    }
+
+    fn complete(&self) {
+        let mut ring = self.ring.borrow_mut();
+        let mut ops = self.ops.borrow_mut();
+        for completion in ring.completion() {
+            let id = completion.user_data();
+            let op = match ops.get_mut(&id) {
+                Some(op) => op,
+                None => continue,
+            };
+            op.finished = !cqueue::more(completion.flags());
+            if op.abandoned {
+                close_accepted(op, &completion);
+                if op.finished {
+                    ops.remove(&id);
+                }
+                continue;
+            }
+            op.completions.push_back(completion);
+            if let Some(waker) = op.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        // The kernel cancels the operations still in flight only after the
+        // ring is closed, so their memory must never be freed.
+        for (_, op) in self.ops.get_mut().drain() {
+            mem::forget(op.data);
+        }
+    }
+}
+
+/// Close the socket returned by an accept nobody waits for.
+fn close_accepted(op: &Operation, completion: &cqueue::Entry) {
+    let is_accept = op
+        .data
+        .as_ref()
+        .is_some_and(|data| data.is::<Box<(libc::sockaddr_vm, socklen_t)>>());
+    if is_accept && completion.result() >= 0 {
+        unsafe { close(completion.result()) };
+    }
+}
+
+/// An operation submitted to a [`Ring`], cancelled when dropped before it
+/// finished.
+struct Op {
+    ring: Rc<Ring>,
+    id: u64,
+}
+
+impl Op {
+    /// Wait for the next completion of the operation.
+    async fn next(&self) -> cqueue::Entry {
+        poll_fn(|cx| {
+            if !self.ring.flush() {
+                // Retry submitting once other tasks had a chance to run.
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let mut ops = self.ring.ops.borrow_mut();
+            let op = ops.get_mut(&self.id).expect("operation in flight");
+            match op.completions.pop_front() {
+                Some(completion) => Poll::Ready(completion),
+                None => {
+                    op.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// The data of the operation, once it finished.
+    fn into_data<T: 'static>(self) -> T {
+        let mut ops = self.ring.ops.borrow_mut();
+        let op = ops.remove(&self.id).expect("operation in flight");
+        debug_assert!(op.finished);
+        *op.data
+            .expect("operation data")
+            .downcast::<T>()
+            .expect("operation data type")
+    }
+}
+
+impl Drop for Op {
+    fn drop(&mut self) {
+        let mut ops = self.ring.ops.borrow_mut();
+        let op = match ops.get_mut(&self.id) {
+            Some(op) => op,
+            None => return,
+        };
+        for completion in mem::take(&mut op.completions) {
+            close_accepted(op, &completion);
+        }
+        if op.finished {
+            ops.remove(&self.id);
+            return;
+        }
+        op.abandoned = true;
+        drop(ops);
+
+        let cancel = opcode::AsyncCancel::new(self.id).build().user_data(IGNORED);
+        // Safety: cancelling refers to no memory. If the queue is full the
+        // operation is not cancelled, but still releases its data once it
+        // completes.
+        if unsafe { self.ring.ring.borrow_mut().submission().push(&cancel) }.is_ok() {
+            self.ring.unsubmitted.set(true);
+            self.ring.flush();
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Transmission without copying the data into the kernel, using
+//! `MSG_ZEROCOPY`.
+//!
+//! The kernel pins the pages of a zero-copy send and reports on the socket's
+//! error queue once it no longer needs them, so the buffers have to be kept
+//! until then.
+
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::mem::{self, size_of};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+
+use libc::*;
+use tokio::io::{AsyncWriteExt, Interest, Ready};
+
+use crate::VsockStream;
+
+// From asm-generic/socket.h, linux/errqueue.h and linux/vm_sockets.h.
+const SO_ZEROCOPY: c_int = 60;
+const SO_EE_ORIGIN_ZEROCOPY: u8 = 5;
+const SOL_VSOCK: c_int = 287;
+const VSOCK_RECVERR: c_int = 1;
+
+/// The zero-copy state of a stream.
+#[derive(Default)]
+pub(crate) struct ZeroCopy {
+    /// Whether `SO_ZEROCOPY` could be enabled, once it was tried.
+    supported: Option<bool>,
+    /// The notification id of the next zero-copy send. The kernel counts
+    /// successful sends starting from 0.
+    next_id: u32,
+    pending: VecDeque<Pending>,
+}
+
+/// A buffer waiting for the completion of the sends `first..end`.
+struct Pending {
+    first: u32,
+    end: u32,
+    completed: u32,
+    /// Whether more of the buffer may still be sent.
+    sending: bool,
+    /// Kept until the kernel no longer uses it.
+    _buf: Box<dyn AsRef<[u8]> + Send + Sync>,
+}
+
+impl Pending {
+    fn sends(&self) -> u32 {
+        self.end.wrapping_sub(self.first)
+    }
+}
+
+impl std::fmt::Debug for ZeroCopy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZeroCopy")
+            .field("supported", &self.supported)
+            .field("next_id", &self.next_id)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl ZeroCopy {
+    fn enable(&mut self, fd: RawFd) -> Result<bool> {
+        if let Some(supported) = self.supported {
+            return Ok(supported);
+        }
+        let one: c_int = 1;
+        let ret = unsafe {
+            setsockopt(
+                fd,
+                SOL_SOCKET,
+                SO_ZEROCOPY,
+                &one as *const _ as *const c_void,
+                size_of::<c_int>() as socklen_t,
+            )
+        };
+        let supported = if ret < 0 {
+            let err = Error::last_os_error();
+            match err.raw_os_error() {
+                Some(ENOPROTOOPT) | Some(EOPNOTSUPP) | Some(EINVAL) => false,
+                _ => return Err(err),
+            }
+        } else {
+            true
+        };
+        self.supported = Some(supported);
+        Ok(supported)
+    }
+
+    /// Release the buffers of all sends the kernel reported as completed.
+    fn reap(&mut self, fd: RawFd) -> Result<()> {
+        loop {
+            // Aligned for `cmsghdr`.
+            let mut control = [0u64; 16];
+            let mut msg: msghdr = unsafe { mem::zeroed() };
+            msg.msg_control = control.as_mut_ptr() as *mut c_void;
+            msg.msg_controllen = mem::size_of_val(&control) as _;
+            if unsafe { recvmsg(fd, &mut msg, MSG_ERRQUEUE | MSG_DONTWAIT) } < 0 {
+                let err = Error::last_os_error();
+                match err.kind() {
+                    ErrorKind::WouldBlock => return Ok(()),
+                    ErrorKind::Interrupted => continue,
+                    _ => return Err(err),
+                }
+            }
+
+            let mut cmsg = unsafe { CMSG_FIRSTHDR(&msg) };
+            while !cmsg.is_null() {
+                let header = unsafe { &*cmsg };
+                let is_recverr = (header.cmsg_level == SOL_VSOCK
+                    && header.cmsg_type == VSOCK_RECVERR)
+                    || (header.cmsg_level == SOL_IP && header.cmsg_type == IP_RECVERR);
+                if is_recverr {
+                    let err: sock_extended_err =
+                        unsafe { ptr::read_unaligned(CMSG_DATA(cmsg) as *const _) };
+                    if err.ee_errno == 0 && err.ee_origin == SO_EE_ORIGIN_ZEROCOPY {
+                        // The range of completed sends, inclusive.
+                        self.complete(err.ee_info, err.ee_data);
+                    }
+                }
+                cmsg = unsafe { CMSG_NXTHDR(&msg, cmsg) };
+            }
+        }
+    }
+
+    fn complete(&mut self, lo: u32, hi: u32) {
+        for pending in &mut self.pending {
+            // Relative to the first send of the buffer, since ids wrap around.
+            let sends = pending.sends();
+            let start = lo.wrapping_sub(pending.first);
+            let stop = hi.wrapping_sub(pending.first);
+            let start = if start > stop { 0 } else { start };
+            if start < sends {
+                pending.completed += stop.min(sends - 1) - start + 1;
+            }
+        }
+        self.release();
+    }
+
+    fn release(&mut self) {
+        self.pending
+            .retain(|pending| pending.sending || pending.completed < pending.sends());
+    }
+
+    /// Forget about sends that were interrupted by dropping their future,
+    /// which `&mut self` rules out at the start of another call.
+    fn settle(&mut self) {
+        for pending in &mut self.pending {
+            pending.sending = false;
+        }
+        self.release();
+    }
+}
+
+impl Drop for ZeroCopy {
+    fn drop(&mut self) {
+        // The kernel may still read from buffers whose sends did not complete,
+        // even once the socket is closed, so they must never be freed.
+        for pending in self.pending.drain(..) {
+            mem::forget(pending);
+        }
+    }
+}
+
+impl VsockStream {
+    /// Send all of `buf` without copying it into the kernel, keeping `buf`
+    /// until the kernel reports that it no longer needs it.
+    ///
+    /// This enables `SO_ZEROCOPY` on the socket and sends with
+    /// `MSG_ZEROCOPY`, which Virtio sockets support since Linux 6.7. On
+    /// older kernels the data is written and `buf` released right away. The
+    /// kernel may still copy small sends and loopback traffic.
+    ///
+    /// Completions are processed on every call; use
+    /// [`zerocopy_completed`](Self::zerocopy_completed) to wait until all
+    /// buffers were released. Buffers the kernel still uses when the stream is
+    /// dropped or turned into a file descriptor are leaked rather than freed,
+    /// so wait for the completions before closing the stream.
+    ///
+    /// ```no_run
+    /// # async fn run(mut stream: tokio_vsock::VsockStream) -> std::io::Result<()> {
+    /// let image = bytes::Bytes::from(std::fs::read("rootfs.img")?);
+    /// stream.send_zerocopy(image).await?;
+    /// stream.zerocopy_completed().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_zerocopy<B>(&mut self, buf: B) -> Result<()>
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        let fd = self.as_raw_fd();
+        let state = self.zerocopy_state();
+        if !state.enable(fd)? {
+            return self.write_all(buf.as_ref()).await;
+        }
+        state.settle();
+        state.reap(fd)?;
+
+        // Boxed first, so that the data stays in place while it is sent.
+        let buf: Box<dyn AsRef<[u8]> + Send + Sync> = Box::new(buf);
+        let data = (*buf).as_ref();
+        let (ptr, len) = (data.as_ptr(), data.len());
+        if len == 0 {
+            return Ok(());
+        }
+        let id = state.next_id;
+        state.pending.push_back(Pending {
+            first: id,
+            end: id,
+            completed: 0,
+            sending: true,
+            _buf: buf,
+        });
+
+        let mut offset = 0;
+        while offset < len {
+            let mut guard = self.async_fd().writable().await?;
+            // Safety: the buffer is owned by the pending entry and its data
+            // does not move while boxed.
+            let remaining = unsafe { ptr.add(offset) };
+            let sent = guard.try_io(|inner| {
+                let ret = unsafe {
+                    send(
+                        inner.as_raw_fd(),
+                        remaining as *const c_void,
+                        len - offset,
+                        MSG_ZEROCOPY,
+                    )
+                };
+                if ret < 0 {
+                    Err(Error::last_os_error())
+                } else {
+                    Ok(ret as usize)
+                }
+            });
+            drop(guard);
+            match sent {
+                Ok(Ok(n)) => {
+                    offset += n;
+                    self.touch();
+                    let state = self.zerocopy_state();
+                    state.next_id = state.next_id.wrapping_add(1);
+                    if let Some(pending) = state.pending.back_mut() {
+                        pending.end = state.next_id;
+                    }
+                }
+                Ok(Err(err)) if err.kind() == ErrorKind::Interrupted => {}
+                // Too much memory is pinned, wait for completions.
+                Ok(Err(err)) if err.raw_os_error() == Some(ENOBUFS) => {
+                    self.wait_for_completions().await?;
+                }
+                Ok(Err(err)) => {
+                    self.zerocopy_state().settle();
+                    return Err(err);
+                }
+                Err(_would_block) => {}
+            }
+        }
+        let state = self.zerocopy_state();
+        state.settle();
+        state.reap(fd)
+    }
+
+    /// Wait until the kernel released all buffers passed to
+    /// [`send_zerocopy`](Self::send_zerocopy).
+    pub async fn zerocopy_completed(&mut self) -> Result<()> {
+        let fd = self.as_raw_fd();
+        self.zerocopy_state().settle();
+        loop {
+            self.zerocopy_state().reap(fd)?;
+            if self.zerocopy_state().pending.is_empty() {
+                return Ok(());
+            }
+            self.wait_for_completions().await?;
+        }
+    }
+
+    /// The number of buffers passed to [`send_zerocopy`](Self::send_zerocopy)
+    /// that the kernel still uses.
+    pub fn zerocopy_pending(&mut self) -> usize {
+        self.zerocopy_state().pending.len()
+    }
+
+    async fn wait_for_completions(&mut self) -> Result<()> {
+        let fd = self.as_raw_fd();
+        let mut guard = self.async_fd().ready(Interest::ERROR).await?;
+        guard.clear_ready_matching(Ready::ERROR);
+        drop(guard);
+        self.zerocopy_state().reap(fd)
+    }
+}
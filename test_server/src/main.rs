@@ -15,9 +15,8 @@
  */
 
 use clap::{crate_authors, crate_version, App, Arg};
-use futures::StreamExt as _;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio_vsock::{VsockListener, VsockAddr};
+use tokio_vsock::{VsockConnectInfo, VsockServer, VsockStream};
 
 /// A simple Virtio socket server that uses Hyper to response to requests.
 #[tokio::main]
@@ -42,37 +41,30 @@ async fn main() -> Result<(), ()> {
         .parse::<u32>()
         .expect("port must be a valid integer");
 
-    let addr = VsockAddr::new(libc::VMADDR_CID_ANY, listen_port);
-    let listener = VsockListener::bind(addr).expect("unable to bind virtio listener");
-
     println!("Listening for connections on port: {}", listen_port);
 
-    let mut incoming = listener.incoming();
-    while let Some(result) = incoming.next().await {
-        match result {
-            Ok(mut stream) => {
-                println!("Got connection ============");
-                tokio::spawn(async move {
-                    loop {
-                        let mut buf = vec![0u8; 5000];
-                        let len = stream.read(&mut buf).await.unwrap();
+    VsockServer::new()
+        .route(listen_port, echo)
+        .serve()
+        .await
+        .expect("unable to serve virtio connections");
+
+    Ok(())
+}
 
-                        if len == 0 {
-                            break;
-                        }
+/// Echo everything received on `stream` back to the peer.
+async fn echo(mut stream: VsockStream, _info: VsockConnectInfo) {
+    println!("Got connection ============");
+    let mut buf = vec![0u8; 5000];
+    loop {
+        let len = match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(len) => len,
+        };
 
-                        buf.resize(len, 0);
-                        println!("Got data: {:?}", &buf);
-                        stream.write_all(&buf).await.unwrap();
-                    }
-                });
-            }
-            Err(e) => {
-                println!("Got error: {:?}", e);
-                return Err(());
-            }
+        println!("Got data: {:?}", &buf[..len]);
+        if stream.write_all(&buf[..len]).await.is_err() {
+            break;
         }
     }
-
-    Ok(())
 }
@@ -0,0 +1,38 @@
+#![cfg(feature = "async-io")]
+
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use futures::StreamExt;
+use tokio_vsock::async_io::{VsockListener, VsockStream};
+use tokio_vsock::{VsockAddr, VMADDR_CID_LOCAL};
+
+#[test]
+fn async_io_echo() {
+    const PORT: u32 = 8017;
+
+    async_io::block_on(async {
+        let addr = VsockAddr::new(VMADDR_CID_LOCAL, PORT);
+        let listener = VsockListener::bind(addr).expect("unable to bind vsock listener");
+        let server = async {
+            let mut stream = listener
+                .incoming()
+                .next()
+                .await
+                .expect("incoming ended")
+                .expect("accept failed");
+            let mut buf = [0u8; 64];
+            let len = stream.read(&mut buf).await.expect("read failed");
+            stream.write_all(&buf[..len]).await.expect("write failed");
+            stream.close().await.expect("close failed");
+        };
+        let client = async {
+            let mut stream = VsockStream::connect(addr).await.expect("connection failed");
+            assert_eq!(stream.peer_addr().expect("no peer address"), addr);
+            assert_eq!(stream.connect_info().peer_addr(), Some(addr));
+            stream.write_all(b"hello smol").await.expect("write failed");
+            let mut echoed = Vec::new();
+            stream.read_to_end(&mut echoed).await.expect("read failed");
+            assert_eq!(echoed, b"hello smol");
+        };
+        futures::join!(server, client);
+    });
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "channel")]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio_vsock::{JsonEncoding, RpcEncoding, RpcError, VsockRpcClient, VsockRpcServer};
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    Add(u32, u32),
+    Sleep(u64),
+    Fail(String),
+}
+
+type Client<E> = VsockRpcClient<Request, u32, E>;
+
+/// Start a server on one end of an in-memory pipe and return a client for the
+/// other end.
+fn connect<E: RpcEncoding>(encoding: E, cancelled: Arc<AtomicBool>) -> Client<E> {
+    let (a, b) = tokio::io::duplex(4096);
+    let server = VsockRpcServer::new(encoding.clone());
+    tokio::spawn(async move {
+        server
+            .serve(b, move |request| {
+                let cancelled = cancelled.clone();
+                async move {
+                    match request {
+                        Request::Add(a, b) => Ok(a + b),
+                        Request::Sleep(ms) => {
+                            let guard = SetOnDrop(cancelled);
+                            tokio::time::sleep(Duration::from_millis(ms)).await;
+                            std::mem::forget(guard);
+                            Ok(0)
+                        }
+                        Request::Fail(msg) => Err(msg),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+    });
+    VsockRpcClient::new(a, encoding)
+}
+
+struct SetOnDrop(Arc<AtomicBool>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+async fn round_trip<E: RpcEncoding>(encoding: E) {
+    let client = connect(encoding, Arc::default());
+
+    let calls = (0..16).map(|i| {
+        let client = client.clone();
+        async move { client.call(Request::Add(i, 1)).await.unwrap() }
+    });
+    let results = futures::future::join_all(calls).await;
+    assert_eq!(results, (1..17).collect::<Vec<_>>());
+
+    match client.call(Request::Fail("no such disk".into())).await {
+        Err(RpcError::Remote(msg)) => assert_eq!(msg, "no such disk"),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn json_round_trip() {
+    round_trip(JsonEncoding).await;
+}
+
+#[cfg(feature = "bincode")]
+#[tokio::test]
+async fn bincode_round_trip() {
+    round_trip(tokio_vsock::BincodeEncoding).await;
+}
+
+#[cfg(feature = "ciborium")]
+#[tokio::test]
+async fn cbor_round_trip() {
+    round_trip(tokio_vsock::CborEncoding).await;
+}
+
+#[tokio::test]
+async fn deadline_cancels_server_handler() {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let client = connect(JsonEncoding, cancelled.clone());
+
+    let deadline = Instant::now() + Duration::from_millis(50);
+    let result = client
+        .call_with_deadline(Request::Sleep(10_000), deadline)
+        .await;
+    assert!(matches!(result, Err(RpcError::DeadlineExceeded)));
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(cancelled.load(Ordering::SeqCst));
+    assert_eq!(client.call(Request::Add(2, 3)).await.unwrap(), 5);
+}
+
+#[tokio::test]
+async fn dropped_call_is_cancelled() {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let client = connect(JsonEncoding, cancelled.clone());
+
+    let call = client.call(Request::Sleep(10_000));
+    assert!(tokio::time::timeout(Duration::from_millis(20), call)
+        .await
+        .is_err());
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(cancelled.load(Ordering::SeqCst));
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "codec")]
+
+use std::io::ErrorKind;
+
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+use tokio_vsock::LengthDelimitedCodec;
+
+#[test]
+fn header_width_and_endianness() {
+    let mut codec = LengthDelimitedCodec::builder()
+        .length_field_length(2)
+        .little_endian()
+        .new_codec();
+    let mut buf = BytesMut::new();
+    codec.encode(Bytes::from_static(b"abc"), &mut buf).unwrap();
+    assert_eq!(&buf[..], b"\x03\x00abc");
+
+    let mut codec = LengthDelimitedCodec::builder()
+        .length_field_length(3)
+        .new_codec();
+    buf.clear();
+    codec.encode(Bytes::from_static(b"abc"), &mut buf).unwrap();
+    assert_eq!(&buf[..], b"\x00\x00\x03abc");
+    assert_eq!(&codec.decode(&mut buf).unwrap().unwrap()[..], b"abc");
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn partial_frames_and_limits() {
+    let mut codec = LengthDelimitedCodec::builder()
+        .max_frame_length(4)
+        .new_codec();
+    let mut buf = BytesMut::from(&b"\x00\x00\x00\x04ab"[..]);
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    buf.extend_from_slice(b"cd\x00\x00");
+    assert_eq!(&codec.decode(&mut buf).unwrap().unwrap()[..], b"abcd");
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+    let mut buf = BytesMut::from(&b"\x00\x00\x00\x05"[..]);
+    let err = codec.decode(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    let err = codec
+        .encode(Bytes::from_static(b"abcde"), &mut buf)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let mut codec = LengthDelimitedCodec::builder()
+        .length_field_length(1)
+        .new_codec();
+    let err = codec
+        .encode(Bytes::from(vec![0u8; 256]), &mut buf)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn stream_and_sink() {
+    let (a, b) = tokio::io::duplex(64);
+    let codec = LengthDelimitedCodec::new();
+    let mut sink = FramedWrite::new(a, codec.clone());
+    let mut stream = FramedRead::new(b, codec);
+
+    let frames: Vec<Bytes> = vec![
+        Bytes::from_static(b"hello"),
+        Bytes::new(),
+        Bytes::from(vec![7u8; 1000]),
+    ];
+    let writer = tokio::spawn({
+        let frames = frames.clone();
+        async move {
+            for frame in frames {
+                sink.send(frame).await.unwrap();
+            }
+        }
+    });
+
+    for expected in frames {
+        let frame = stream.next().await.unwrap().unwrap();
+        assert_eq!(frame, expected);
+    }
+    writer.await.unwrap();
+    assert!(stream.next().await.is_none());
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "futures-io")]
+
+use std::os::fd::OwnedFd;
+
+use futures::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio_vsock::VsockStream;
+
+/// A connected pair of streams over a Unix socket pair, which the stream
+/// operations do not tell apart from Virtio sockets.
+fn pair() -> (VsockStream, VsockStream) {
+    let (a, b) = std::os::unix::net::UnixStream::pair().expect("unable to create socket pair");
+    (
+        VsockStream::from(OwnedFd::from(a)),
+        VsockStream::from(OwnedFd::from(b)),
+    )
+}
+
+#[tokio::test]
+async fn futures_io_stream() {
+    let (mut a, b) = pair();
+
+    AsyncWriteExt::write_all(&mut a, b"hello\nworld\n")
+        .await
+        .expect("write failed");
+    AsyncWriteExt::close(&mut a).await.expect("close failed");
+
+    let mut lines = BufReader::new(b).lines();
+    let mut received = Vec::new();
+    while let Some(line) = futures::StreamExt::next(&mut lines).await {
+        received.push(line.expect("read failed"));
+    }
+    assert_eq!(received, ["hello", "world"]);
+}
+
+#[tokio::test]
+async fn futures_io_owned_halves() {
+    let (a, b) = pair();
+    let (mut a_read, mut a_write) = a.into_split();
+    let (mut b_read, mut b_write) = b.into_split();
+
+    AsyncWriteExt::write_all(&mut a_write, b"ping")
+        .await
+        .expect("write failed");
+    let mut buf = [0u8; 4];
+    AsyncReadExt::read_exact(&mut b_read, &mut buf)
+        .await
+        .expect("read failed");
+    assert_eq!(&buf, b"ping");
+
+    AsyncWriteExt::write_all(&mut b_write, b"pong")
+        .await
+        .expect("write failed");
+    AsyncWriteExt::close(&mut b_write)
+        .await
+        .expect("close failed");
+    let mut reply = Vec::new();
+    AsyncReadExt::read_to_end(&mut a_read, &mut reply)
+        .await
+        .expect("read failed");
+    assert_eq!(reply, b"pong");
+}
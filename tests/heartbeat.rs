@@ -0,0 +1,59 @@
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+use tokio_vsock::{HeartbeatStream, PeerDead};
+
+#[tokio::test]
+async fn data_passes_and_rtt_is_reported() {
+    let (a, b) = duplex(64 * 1024);
+    let interval = Duration::from_millis(10);
+    let mut client = HeartbeatStream::new(a, interval);
+    let mut server = HeartbeatStream::new(b, interval);
+    let handle = client.handle();
+
+    let echo = tokio::spawn(async move {
+        let mut buf = vec![0u8; 100 * 1024];
+        server.read_exact(&mut buf).await.unwrap();
+        server.write_all(&buf).await.unwrap();
+        server.flush().await.unwrap();
+        // Keep answering pings until the client is done.
+        let mut rest = Vec::new();
+        server.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    });
+
+    // Let a few heartbeats pass while both ends are idle in a read.
+    let mut byte = [0u8; 1];
+    let idle = tokio::time::timeout(Duration::from_millis(50), client.read(&mut byte)).await;
+    assert!(idle.is_err());
+    assert!(handle.last_rtt().is_some());
+
+    let data: Vec<u8> = (0..100 * 1024).map(|i| i as u8).collect();
+    client.write_all(&data).await.unwrap();
+    client.flush().await.unwrap();
+    let mut received = vec![0u8; data.len()];
+    client.read_exact(&mut received).await.unwrap();
+    assert!(received == data);
+
+    assert_eq!(handle.missed_heartbeats(), 0);
+
+    client.shutdown().await.unwrap();
+    echo.await.unwrap();
+}
+
+#[tokio::test]
+async fn silent_peer_is_reported_dead() {
+    let (a, _silent_peer) = duplex(64 * 1024);
+    let mut stream = HeartbeatStream::new(a, Duration::from_millis(10)).max_missed(2);
+
+    let mut buf = [0u8; 1];
+    let err = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf))
+        .await
+        .expect("peer was not reported dead")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    let dead = err.get_ref().unwrap().downcast_ref::<PeerDead>().unwrap();
+    assert_eq!(dead.missed(), 2);
+    assert!(stream.last_rtt().is_none());
+}
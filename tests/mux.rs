@@ -0,0 +1,180 @@
+use std::io::ErrorKind;
+
+use futures::StreamExt;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+use tokio_vsock::mux::{MuxConfig, MuxSession};
+
+fn pair(config: MuxConfig) -> (MuxSession, MuxSession) {
+    let (a, b) = duplex(64 * 1024);
+    (
+        MuxSession::client(a, config.clone()),
+        MuxSession::server(b, config),
+    )
+}
+
+#[tokio::test]
+async fn open_accept_and_echo() {
+    let (client, mut server) = pair(MuxConfig::new());
+
+    let echo = tokio::spawn(async move {
+        while let Some(mut stream) = server.next().await {
+            tokio::spawn(async move {
+                let mut buf = Vec::new();
+                stream.read_to_end(&mut buf).await.unwrap();
+                stream.write_all(&buf).await.unwrap();
+            });
+        }
+    });
+
+    let mut first = client.open_stream().unwrap();
+    let mut second = client.open_stream().unwrap();
+    assert_eq!((first.id(), second.id()), (1, 3));
+
+    second.write_all(b"second").await.unwrap();
+    first.write_all(b"first").await.unwrap();
+    first.shutdown().await.unwrap();
+    second.shutdown().await.unwrap();
+
+    let mut buf = Vec::new();
+    first.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, b"first");
+    buf.clear();
+    second.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, b"second");
+
+    drop(client);
+    echo.await.unwrap();
+}
+
+#[tokio::test]
+async fn flow_control_beyond_window() {
+    const LEN: usize = 1024 * 1024;
+
+    let (client, server) = pair(MuxConfig::new().max_frame_size(4096));
+
+    let data: Vec<u8> = (0..LEN).map(|i| i as u8).collect();
+    let mut stream = client.open_stream().unwrap();
+    let writer = tokio::spawn({
+        let data = data.clone();
+        async move {
+            stream.write_all(&data).await.unwrap();
+            stream.shutdown().await.unwrap();
+            stream
+        }
+    });
+
+    let mut accepted = server.accept().await.unwrap();
+    let mut received = Vec::new();
+    accepted.read_to_end(&mut received).await.unwrap();
+    assert_eq!(received.len(), LEN);
+    assert!(received == data);
+    writer.await.unwrap();
+}
+
+#[tokio::test]
+async fn reset_and_session_close() {
+    let (client, server) = pair(MuxConfig::new());
+
+    let mut stream = client.open_stream().unwrap();
+    stream.write_all(b"hello").await.unwrap();
+    let mut accepted = server.accept().await.unwrap();
+    let mut buf = [0u8; 5];
+    accepted.read_exact(&mut buf).await.unwrap();
+
+    stream.reset();
+    let err = accepted.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    assert_eq!(
+        stream.write(b"x").await.unwrap_err().kind(),
+        ErrorKind::ConnectionReset
+    );
+
+    let mut open = client.open_stream().unwrap();
+    let _accepted = server.accept().await.unwrap();
+    drop(server);
+    assert_eq!(
+        open.read(&mut buf).await.unwrap_err().kind(),
+        ErrorKind::ConnectionAborted
+    );
+    assert!(client.is_closed());
+    assert!(client.open_stream().is_err());
+}
+
+#[tokio::test]
+async fn keepalive_detects_dead_peer() {
+    use std::time::Duration;
+
+    let (a, _silent_peer) = duplex(64 * 1024);
+    let session = MuxSession::client(
+        a,
+        MuxConfig::new().keepalive_interval(Some(Duration::from_millis(20))),
+    );
+    let mut stream = session.open_stream().unwrap();
+
+    let mut buf = [0u8; 1];
+    let err = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf))
+        .await
+        .expect("session was not closed")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(session.is_closed());
+}
+
+#[tokio::test]
+async fn rejects_syn_with_invalid_stream_id() {
+    // A window update with FLAG_SYN for `id`, as a misbehaving peer sends it.
+    fn syn(id: u32) -> Vec<u8> {
+        let mut frame = vec![0, 1, 0, 1];
+        frame.extend_from_slice(&id.to_be_bytes());
+        frame.extend_from_slice(&0u32.to_be_bytes());
+        frame
+    }
+
+    let (a, mut peer) = duplex(64 * 1024);
+    let session = MuxSession::client(a, MuxConfig::new());
+
+    // Our own parity, id 0, and an id that does not increase.
+    for id in [3, 0, 4, 2] {
+        peer.write_all(&syn(id)).await.unwrap();
+    }
+
+    let mut frame = [0u8; 12];
+    let mut accepted = Vec::new();
+    let mut reset = Vec::new();
+    for _ in 0..4 {
+        peer.read_exact(&mut frame).await.unwrap();
+        let flags = u16::from_be_bytes([frame[2], frame[3]]);
+        let id = u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]);
+        if flags & 0x8 != 0 {
+            reset.push(id);
+        } else {
+            accepted.push(id);
+        }
+    }
+    assert_eq!(accepted, [4]);
+    assert_eq!(reset, [3, 0, 2]);
+
+    let stream = session.accept().await.unwrap();
+    assert_eq!(stream.id(), 4);
+    // Our own ids stay ours.
+    assert_eq!(session.open_stream().unwrap().id(), 1);
+}
+
+#[tokio::test]
+async fn stops_reading_while_replies_are_not_read() {
+    use std::time::Duration;
+
+    let (a, mut peer) = duplex(1024);
+    let _session = MuxSession::client(a, MuxConfig::new());
+
+    // Pings, each of which the session answers, from a peer that never reads
+    // the answers.
+    let mut pings = Vec::new();
+    for id in 0u32..10_000 {
+        pings.extend_from_slice(&[0, 2, 0, 1]);
+        pings.extend_from_slice(&0u32.to_be_bytes());
+        pings.extend_from_slice(&id.to_be_bytes());
+    }
+    let flood = tokio::time::timeout(Duration::from_millis(500), peer.write_all(&pings)).await;
+    assert!(flood.is_err(), "the session kept reading pings");
+}
@@ -0,0 +1,17 @@
+use std::ops::Bound;
+
+use tokio_vsock::{PeerPolicy, VsockAddr};
+
+#[test]
+fn ranges_starting_after_the_last_value_are_empty() {
+    let above_max = (Bound::Excluded(u32::MAX), Bound::Unbounded);
+    let policy = PeerPolicy::deny_all()
+        .allow(above_max, ..)
+        .allow(.., above_max);
+    assert!(!policy.is_allowed(&VsockAddr::new(u32::MAX, 8000)));
+    assert!(!policy.is_allowed(&VsockAddr::new(3, u32::MAX)));
+
+    let policy =
+        PeerPolicy::deny_all().allow((Bound::Excluded(u32::MAX - 1), Bound::Unbounded), ..);
+    assert!(policy.is_allowed(&VsockAddr::new(u32::MAX, 8000)));
+}
@@ -0,0 +1,80 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio_vsock::proxy::splice_bidirectional;
+
+#[tokio::test]
+async fn forwards_both_directions_with_half_close() {
+    const LEN: usize = 1024 * 1024;
+
+    let (mut client, mut proxy_in) = UnixStream::pair().unwrap();
+    let (mut proxy_out, mut server) = UnixStream::pair().unwrap();
+    let proxy = tokio::spawn(async move {
+        splice_bidirectional(&mut proxy_in, &mut proxy_out)
+            .await
+            .unwrap()
+    });
+
+    let request: Vec<u8> = (0..LEN).map(|i| (i % 253) as u8).collect();
+    let writer = tokio::spawn({
+        let request = request.clone();
+        async move {
+            client.write_all(&request).await.unwrap();
+            client.shutdown().await.unwrap();
+            let mut reply = Vec::new();
+            client.read_to_end(&mut reply).await.unwrap();
+            reply
+        }
+    });
+
+    // The request ends before the reply is sent.
+    let mut received = Vec::new();
+    server.read_to_end(&mut received).await.unwrap();
+    assert!(received == request);
+    server.write_all(b"done").await.unwrap();
+    server.shutdown().await.unwrap();
+
+    assert_eq!(writer.await.unwrap(), b"done");
+    assert_eq!(proxy.await.unwrap(), (LEN as u64, 4));
+}
+
+#[tokio::test]
+async fn empty_directions_complete() {
+    let (client, mut proxy_in) = UnixStream::pair().unwrap();
+    let (mut proxy_out, server) = UnixStream::pair().unwrap();
+    drop((client, server));
+    let counts = splice_bidirectional(&mut proxy_in, &mut proxy_out)
+        .await
+        .unwrap();
+    assert_eq!(counts, (0, 0));
+}
+
+#[tokio::test]
+async fn shuts_down_vsock_streams_through_the_stream() {
+    use std::os::fd::OwnedFd;
+    use tokio_vsock::VsockStream;
+
+    let (client, proxy_in) = std::os::unix::net::UnixStream::pair().unwrap();
+    let mut client = VsockStream::from(OwnedFd::from(client));
+    let mut proxy_in = VsockStream::from(OwnedFd::from(proxy_in));
+    // The end of the reply waits for the send queue to drain.
+    proxy_in.set_drain_on_flush(true);
+    let (mut proxy_out, mut server) = UnixStream::pair().unwrap();
+    let proxy = tokio::spawn(async move {
+        splice_bidirectional(&mut proxy_in, &mut proxy_out)
+            .await
+            .unwrap()
+    });
+
+    client.write_all(b"ping").await.unwrap();
+    AsyncWriteExt::shutdown(&mut client).await.unwrap();
+    let mut request = Vec::new();
+    server.read_to_end(&mut request).await.unwrap();
+    assert_eq!(request, b"ping");
+    server.write_all(b"pong").await.unwrap();
+    server.shutdown().await.unwrap();
+
+    let mut reply = Vec::new();
+    client.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(reply, b"pong");
+    assert_eq!(proxy.await.unwrap(), (4, 4));
+}
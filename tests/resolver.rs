@@ -0,0 +1,70 @@
+use std::fs;
+use std::io::ErrorKind;
+
+use std::sync::Arc;
+
+use tokio_vsock::{FileResolver, StaticResolver, VsockResolver, VsockStream, VMADDR_CID_HOST};
+
+#[test]
+fn static_resolver() {
+    let resolver = StaticResolver::new().with_host("builder", 3);
+
+    let addr = resolver.resolve_addr("vsock://builder:8000").unwrap();
+    assert_eq!((addr.cid(), addr.port()), (3, 8000));
+
+    let addr = resolver.resolve_addr("vsock:host:8000").unwrap();
+    assert_eq!((addr.cid(), addr.port()), (VMADDR_CID_HOST, 8000));
+
+    let err = resolver.resolve_addr("vsock://runner:8000").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn file_resolver_reloads_on_change() {
+    let path = std::env::temp_dir().join(format!("vsock-hosts-{}", std::process::id()));
+    fs::write(&path, "# cid names\n3 builder builder.local\n").unwrap();
+
+    let resolver = FileResolver::new(&path);
+    assert_eq!(resolver.resolve("builder").unwrap(), Some(3));
+    assert_eq!(resolver.resolve("builder.local").unwrap(), Some(3));
+    assert_eq!(resolver.resolve("runner").unwrap(), None);
+
+    fs::write(&path, "4 builder\n5 runner # recreated\n").unwrap();
+    assert_eq!(resolver.resolve("builder").unwrap(), Some(4));
+    assert_eq!(resolver.resolve("runner").unwrap(), Some(5));
+
+    // Rewritten at the same size, likely within the same timestamp tick.
+    fs::write(&path, "6 builder\n5 runner # recreated\n").unwrap();
+    assert_eq!(resolver.resolve("builder").unwrap(), Some(6));
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(resolver.resolve("builder").unwrap(), None);
+}
+
+/// Blocks until the runtime got to run another task.
+#[derive(Debug)]
+struct WaitingResolver {
+    ran: std::sync::Mutex<std::sync::mpsc::Receiver<()>>,
+}
+
+impl VsockResolver for WaitingResolver {
+    fn resolve(&self, _name: &str) -> std::io::Result<Option<u32>> {
+        let ran = self.ran.lock().unwrap();
+        ran.recv_timeout(std::time::Duration::from_secs(5))
+            .expect("lookup blocked the runtime");
+        Ok(Some(VMADDR_CID_HOST))
+    }
+}
+
+#[tokio::test]
+async fn connect_looks_up_names_off_the_runtime() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    tokio_vsock::set_default_resolver(Arc::new(WaitingResolver {
+        ran: std::sync::Mutex::new(rx),
+    }));
+
+    let other = tokio::spawn(async move { tx.send(()).unwrap() });
+    // Without vsock support the connection itself fails, after the lookup.
+    let _ = VsockStream::connect("vsock://builder:8000").await;
+    other.await.unwrap();
+}
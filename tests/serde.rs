@@ -0,0 +1,60 @@
+#![cfg(feature = "serde")]
+
+use std::time::UNIX_EPOCH;
+
+use tokio_vsock::{VsockConnectInfo, VsockEndpoint, VMADDR_CID_ANY, VMADDR_CID_HOST};
+
+#[test]
+fn endpoint_from_string() {
+    let endpoint: VsockEndpoint = serde_json::from_str(r#""vsock://3:1024""#).unwrap();
+    assert_eq!(endpoint, VsockEndpoint::new(3, 1024));
+
+    let endpoint: VsockEndpoint = serde_json::from_str(r#""vsock:host:8000""#).unwrap();
+    assert_eq!(endpoint, VsockEndpoint::new(VMADDR_CID_HOST, 8000));
+
+    assert!(serde_json::from_str::<VsockEndpoint>(r#""tcp://3:1024""#).is_err());
+}
+
+#[test]
+fn endpoint_from_struct() {
+    let endpoint: VsockEndpoint = serde_json::from_str(r#"{"cid": 3, "port": 1024}"#).unwrap();
+    assert_eq!(endpoint, VsockEndpoint::new(3, 1024));
+
+    let endpoint: VsockEndpoint = serde_json::from_str(r#"{"cid": "any", "port": 80}"#).unwrap();
+    assert_eq!(endpoint, VsockEndpoint::new(VMADDR_CID_ANY, 80));
+
+    assert!(serde_json::from_str::<VsockEndpoint>(r#"{"cid": "guest", "port": 80}"#).is_err());
+    assert!(serde_json::from_str::<VsockEndpoint>(r#"{"cid": 3}"#).is_err());
+}
+
+#[test]
+fn endpoint_to_string() {
+    let json = serde_json::to_string(&VsockEndpoint::new(3, 1024)).unwrap();
+    assert_eq!(json, r#""vsock://3:1024""#);
+}
+
+#[test]
+fn connect_info_round_trip() {
+    let json = r#"{
+        "peer_addr": "vsock://3:1024",
+        "local_addr": null,
+        "connected_at": { "secs_since_epoch": 1700000000, "nanos_since_epoch": 0 },
+        "connection_id": 7,
+        "transport": "host_to_guest"
+    }"#;
+    let info: VsockConnectInfo = serde_json::from_str(json).unwrap();
+    assert_eq!(info.peer_addr().map(|addr| addr.cid()), Some(3));
+    assert_eq!(info.local_addr(), None);
+    assert_eq!(
+        info.connected_at()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        1_700_000_000
+    );
+    assert_eq!(info.connection_id(), 7);
+
+    let round_trip: VsockConnectInfo =
+        serde_json::from_str(&serde_json::to_string(&info).unwrap()).unwrap();
+    assert_eq!(round_trip, info);
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "io-uring")]
+
+use std::os::fd::OwnedFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use tokio_vsock::uring::{VsockListener, VsockStream};
+use tokio_vsock::{VsockAddr, VMADDR_CID_LOCAL};
+
+#[test]
+fn uring_echo() {
+    const PORT: u32 = 8016;
+
+    tokio_uring::start(async {
+        let addr = VsockAddr::new(VMADDR_CID_LOCAL, PORT);
+        let listener = VsockListener::bind(addr).expect("unable to bind vsock listener");
+        let server = tokio_uring::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("accept failed");
+            let (read, buf) = stream.read(vec![0u8; 64]).await;
+            let len = read.expect("read failed");
+            let (written, _) = stream.write_all(buf[..len].to_vec()).await;
+            written.expect("write failed");
+        });
+
+        let stream = VsockStream::connect(addr).await.expect("connection failed");
+        assert_eq!(stream.peer_addr().expect("no peer address"), addr);
+        let (sent, _) = stream.send_zc(b"hello uring".to_vec()).await;
+        assert_eq!(sent.expect("send failed"), 11);
+        let (read, buf) = stream.read(vec![0u8; 64]).await;
+        let len = read.expect("read failed");
+        assert_eq!(&buf[..len], b"hello uring");
+        server.await.expect("server panicked");
+    });
+}
+
+// The io_uring operations do not depend on the address family, so Unix sockets
+// stand in for Virtio sockets below.
+
+#[test]
+fn uring_send_zc_releases_buffer() {
+    tokio_uring::start(async {
+        let (a, b) = UnixStream::pair().expect("unable to create socket pair");
+        let a = VsockStream::from_stream(tokio_vsock::VsockStream::from(OwnedFd::from(a)))
+            .expect("unable to hand over stream");
+        let b = VsockStream::from_stream(tokio_vsock::VsockStream::from(OwnedFd::from(b)))
+            .expect("unable to hand over stream");
+
+        // Unix sockets do not support zero-copy sends, which falls back to a
+        // regular write.
+        let (sent, buf) = a.send_zc(vec![7u8; 4096]).await;
+        let sent = sent.expect("send failed");
+        assert_eq!(buf.len(), 4096);
+        let mut received = 0;
+        let mut buf = vec![0u8; 4096];
+        while received < sent {
+            let (read, filled) = b.read(buf).await;
+            received += read.expect("read failed");
+            buf = filled;
+        }
+        assert_eq!(received, 4096);
+    });
+}
+
+#[test]
+fn uring_accept_after_cancelled_accept() {
+    tokio_uring::start(async {
+        let path = std::env::temp_dir().join(format!("tokio-vsock-uring-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).expect("unable to bind listener");
+        let listener =
+            VsockListener::from(tokio_vsock::VsockListener::from(OwnedFd::from(listener)));
+
+        // A dropped accept is cancelled and does not take the next connection.
+        let mut accept = Box::pin(listener.accept());
+        assert!(futures::poll!(accept.as_mut()).is_pending());
+        drop(accept);
+
+        let _client = UnixStream::connect(&path).expect("connection failed");
+        listener.accept().await.expect("accept failed");
+        let _ = std::fs::remove_file(&path);
+    });
+}
@@ -190,3 +190,536 @@ async fn into_split_vsock() {
     // Assert that the halfs can be merged together again
     let _ = read_half.unsplit(write_half);
 }
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn incoming_max_connections_and_drain() {
+    use futures::StreamExt;
+    use std::time::Duration;
+    use tokio_vsock::VsockIncoming;
+
+    const PORT: u32 = 8003;
+
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let listener = VsockListener::bind(addr).expect("connection failed");
+    let mut incoming = VsockIncoming::new(listener).max_connections(1);
+    let handle = incoming.handle();
+
+    let _first_client = VsockStream::connect(addr).await.expect("connection failed");
+    let first = incoming
+        .next()
+        .await
+        .expect("incoming ended")
+        .expect("failed to accept connection");
+    assert_eq!(handle.active_connections(), 1);
+
+    // The second connection must wait until the first one is released.
+    let _second_client = VsockStream::connect(addr).await.expect("connection failed");
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), incoming.next())
+            .await
+            .is_err(),
+        "accepted a connection above the limit"
+    );
+
+    drop(first);
+    let second = incoming
+        .next()
+        .await
+        .expect("incoming ended")
+        .expect("failed to accept connection");
+
+    assert!(!handle.drain(Duration::from_millis(100)).await);
+    assert!(incoming.next().await.is_none());
+
+    drop(second);
+    assert!(handle.drain(Duration::from_millis(100)).await);
+    assert_eq!(handle.active_connections(), 0);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn either_listener_vsock_and_tcp() {
+    use tokio_vsock::{EitherAddr, EitherListener, EitherStream};
+
+    const MSG: &[u8] = b"either";
+    const PORT: u32 = 8004;
+
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let vsock = VsockListener::bind(addr).expect("connection failed");
+    let tcp = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind tcp listener");
+    let tcp_addr = tcp.local_addr().expect("no local address");
+    let listener = EitherListener::new(vsock, tcp);
+
+    let mut tcp_client = tokio::net::TcpStream::connect(tcp_addr)
+        .await
+        .expect("connection failed");
+    let (mut stream, peer) = listener.accept().await.expect("failed to accept");
+    assert!(matches!(stream, EitherStream::Tcp(_)));
+    assert!(matches!(peer, EitherAddr::Tcp(_)));
+    tcp_client.write_all(MSG).await.expect("write failed");
+    let mut read_buf = [0u8; 32];
+    let read_len = stream.read(&mut read_buf).await.expect("read failed");
+    assert_eq!(&read_buf[..read_len], MSG);
+
+    let mut vsock_client = VsockStream::connect(addr).await.expect("connection failed");
+    let (mut stream, peer) = listener.accept().await.expect("failed to accept");
+    assert!(matches!(stream, EitherStream::Vsock(_)));
+    assert!(matches!(peer, EitherAddr::Vsock(_)));
+    vsock_client.write_all(MSG).await.expect("write failed");
+    let read_len = stream.read(&mut read_buf).await.expect("read failed");
+    assert_eq!(&read_buf[..read_len], MSG);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn accept_filter_per_cid_limit() {
+    use std::time::Duration;
+    use tokio_vsock::{AcceptFilter, PeerPolicy};
+
+    const PORT: u32 = 8005;
+
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let mut listener = VsockListener::bind(addr).expect("connection failed");
+    let filter = AcceptFilter::new()
+        .policy(PeerPolicy::deny_all().allow_cid(tokio_vsock::VMADDR_CID_LOCAL))
+        .max_connections_per_cid(1);
+    listener.set_accept_filter(filter.clone());
+
+    let _first_client = VsockStream::connect(addr).await.expect("connection failed");
+    let (first, _) = listener.accept().await.expect("failed to accept");
+    assert_eq!(
+        filter.active_connections_for(tokio_vsock::VMADDR_CID_LOCAL),
+        1
+    );
+
+    // The second connection exceeds the quota and is closed by the listener.
+    let mut second_client = VsockStream::connect(addr).await.expect("connection failed");
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), listener.accept())
+            .await
+            .is_err(),
+        "accepted a connection above the quota"
+    );
+    let mut read_buf = [0u8; 1];
+    assert!(matches!(
+        second_client.read(&mut read_buf).await,
+        Ok(0) | Err(_)
+    ));
+
+    drop(first);
+    assert_eq!(filter.active_connections(), 0);
+    let _third_client = VsockStream::connect(addr).await.expect("connection failed");
+    let (_third, _) = listener.accept().await.expect("failed to accept");
+    assert_eq!(filter.active_connections(), 1);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn connect_info_vsock() {
+    use tokio_vsock::VsockTransport;
+
+    const PORT: u32 = 8006;
+
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let listener = VsockListener::bind(addr).expect("connection failed");
+
+    let client = VsockStream::connect(addr).await.expect("connection failed");
+    let (stream, peer) = listener.accept().await.expect("failed to accept");
+
+    let info = stream.connect_info();
+    assert_eq!(info.peer_addr(), Some(peer));
+    assert_eq!(info.local_addr().map(|addr| addr.port()), Some(PORT));
+    assert_eq!(info.transport(), VsockTransport::Loopback);
+
+    let client_info = client.connect_info();
+    assert_eq!(client_info.peer_addr(), Some(addr));
+    assert_eq!(client_info.local_addr(), Some(peer));
+    assert!(client_info.connection_id() < info.connection_id());
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn multi_listener_tags_local_port() {
+    use tokio_vsock::VsockMultiListener;
+
+    const PORTS: std::ops::Range<u32> = 8007..8009;
+
+    let listener =
+        VsockMultiListener::bind(tokio_vsock::VMADDR_CID_LOCAL, PORTS).expect("connection failed");
+    assert_eq!(listener.local_addrs().expect("no local address").len(), 2);
+
+    for port in PORTS.rev() {
+        let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, port);
+        let mut client = VsockStream::connect(addr).await.expect("connection failed");
+        let (mut stream, _) = listener.accept().await.expect("failed to accept");
+        assert_eq!(
+            stream.connect_info().local_addr().map(|addr| addr.port()),
+            Some(port)
+        );
+        client.write_all(b"multi").await.expect("write failed");
+        let mut read_buf = [0u8; 5];
+        stream.read_exact(&mut read_buf).await.expect("read failed");
+        assert_eq!(&read_buf, b"multi");
+    }
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn server_idle_timeout_and_shutdown() {
+    use std::time::Duration;
+    use tokio_vsock::{VsockConnectInfo, VsockServer};
+
+    const PORT: u32 = 8009;
+
+    async fn echo(mut stream: VsockStream, _info: VsockConnectInfo) {
+        let mut buf = [0u8; 64];
+        while let Ok(len) = stream.read(&mut buf).await {
+            if len == 0 || stream.write_all(&buf[..len]).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(
+        VsockServer::new()
+            .cid(tokio_vsock::VMADDR_CID_LOCAL)
+            .route(PORT, echo)
+            .idle_timeout(Duration::from_millis(100))
+            .serve_with_shutdown(
+                async {
+                    let _ = shutdown_rx.await;
+                },
+                Duration::from_millis(100),
+            ),
+    );
+    tokio::task::yield_now().await;
+
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let mut client = VsockStream::connect(addr).await.expect("connection failed");
+    client.write_all(b"ping").await.expect("write failed");
+    let mut read_buf = [0u8; 4];
+    client.read_exact(&mut read_buf).await.expect("read failed");
+    assert_eq!(&read_buf, b"ping");
+
+    // The server closes the connection once it has been idle for too long.
+    let closed = tokio::time::timeout(Duration::from_secs(1), client.read(&mut read_buf))
+        .await
+        .expect("idle connection was not closed");
+    assert!(matches!(closed, Ok(0) | Err(_)));
+
+    shutdown_tx.send(()).expect("server stopped early");
+    server
+        .await
+        .expect("server panicked")
+        .expect("server failed");
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn read_write_and_idle_timeouts() {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    const PORT: u32 = 8010;
+
+    let listener = VsockListener::bind(VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT))
+        .expect("unable to bind vsock listener");
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let (client, server) = tokio::join!(VsockStream::connect(addr), listener.accept());
+    let mut client = client.expect("connection failed");
+    let (server, _) = server.expect("accept failed");
+
+    client.set_read_timeout(Some(Duration::from_millis(50)));
+    assert_eq!(client.read_timeout(), Some(Duration::from_millis(50)));
+    let mut buf = [0u8; 4];
+    let err = client.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    // Timeouts set on a half apply to the stream.
+    let (mut rd, mut wr) = server.into_split();
+    rd.set_idle_timeout(Some(Duration::from_millis(100)));
+    wr.write_all(b"ping").await.expect("write failed");
+    client.read_exact(&mut buf).await.expect("read failed");
+    assert_eq!(&buf, b"ping");
+
+    let err = rd.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    // The idle connection was shut down for the peer as well.
+    client.set_read_timeout(None);
+    assert_eq!(client.read(&mut buf).await.expect("read failed"), 0);
+    let stream = rd.unsplit(wr);
+    assert_eq!(stream.idle_timeout(), Some(Duration::from_millis(100)));
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn read_timeout_restarts_after_cancelled_read() {
+    use std::os::fd::OwnedFd;
+    use std::time::Duration;
+
+    let (a, b) = std::os::unix::net::UnixStream::pair().expect("unable to create socket pair");
+    let mut stream = VsockStream::from(OwnedFd::from(a));
+    let mut peer = VsockStream::from(OwnedFd::from(b));
+    stream.set_read_timeout(Some(Duration::from_millis(300)));
+
+    // The read is dropped while waiting, 100ms before its deadline.
+    let mut buf = [0u8; 4];
+    tokio::select! {
+        _ = stream.read(&mut buf) => panic!("read completed without data"),
+        _ = tokio::time::sleep(Duration::from_millis(200)) => {}
+    }
+
+    // The next read waits past the deadline of the dropped one.
+    let write = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        peer.write_all(b"ping").await
+    };
+    let (read, written) = tokio::join!(stream.read_exact(&mut buf), write);
+    written.expect("write failed");
+    read.expect("read failed");
+    assert_eq!(&buf, b"ping");
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn closed_without_reading() {
+    use std::time::Duration;
+
+    const PORT: u32 = 8011;
+
+    let listener = VsockListener::bind(VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT))
+        .expect("unable to bind vsock listener");
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let (client, server) = tokio::join!(VsockStream::connect(addr), listener.accept());
+    let client = client.expect("connection failed");
+    let (mut server, _) = server.expect("accept failed");
+
+    let (mut rd, wr) = client.into_split();
+    assert!(!wr.is_read_closed().expect("poll failed"));
+    server.write_all(b"bye").await.expect("write failed");
+    drop(server);
+
+    tokio::time::timeout(Duration::from_secs(1), wr.closed())
+        .await
+        .expect("close was not noticed")
+        .expect("waiting for close failed");
+    assert!(rd.is_read_closed().expect("poll failed"));
+    // The data sent before closing is still there.
+    let mut buf = Vec::new();
+    rd.read_to_end(&mut buf).await.expect("read failed");
+    assert_eq!(buf, b"bye");
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn queue_lengths_and_draining_flush() {
+    const PORT: u32 = 8012;
+
+    let listener = VsockListener::bind(VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT))
+        .expect("unable to bind vsock listener");
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let (client, server) = tokio::join!(VsockStream::connect(addr), listener.accept());
+    let mut client = client.expect("connection failed");
+    let (server, _) = server.expect("accept failed");
+
+    client.set_drain_on_flush(true);
+    assert!(client.drain_on_flush());
+    client.write_all(b"queued").await.expect("write failed");
+    client.flush().await.expect("flush failed");
+    assert_eq!(client.unsent_bytes().expect("SIOCOUTQ failed"), 0);
+
+    let (mut rd, _wr) = server.into_split();
+    assert_eq!(rd.unread_bytes().expect("SIOCINQ failed"), 6);
+    let mut buf = [0u8; 6];
+    rd.read_exact(&mut buf).await.expect("read failed");
+    assert_eq!(rd.unread_bytes().expect("SIOCINQ failed"), 0);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn close_gracefully_and_linger() {
+    use std::time::Duration;
+
+    const PORT: u32 = 8013;
+
+    let listener = VsockListener::bind(VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT))
+        .expect("unable to bind vsock listener");
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+
+    // The peer reads everything and closes its end.
+    let (client, server) = tokio::join!(VsockStream::connect(addr), listener.accept());
+    let mut client = client.expect("connection failed");
+    let (mut server, _) = server.expect("accept failed");
+    server
+        .set_linger(Some(Duration::from_secs(1)))
+        .expect("SO_LINGER failed");
+    assert_eq!(
+        server.linger().expect("SO_LINGER failed"),
+        Some(Duration::from_secs(1))
+    );
+    server.write_all(b"bye").await.expect("write failed");
+    let peer = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.expect("read failed");
+        buf
+    });
+    let acked = server
+        .close_gracefully(Duration::from_secs(1))
+        .await
+        .expect("close failed");
+    assert!(acked);
+    assert_eq!(peer.await.expect("peer panicked"), b"bye");
+
+    // The peer keeps its end open.
+    let (client, server) = tokio::join!(VsockStream::connect(addr), listener.accept());
+    let _client = client.expect("connection failed");
+    let (server, _) = server.expect("accept failed");
+    let acked = server
+        .close_gracefully(Duration::from_millis(50))
+        .await
+        .expect("close failed");
+    assert!(!acked);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn linger_rounds_up_to_whole_seconds() {
+    use std::os::fd::OwnedFd;
+    use std::time::Duration;
+
+    // SO_LINGER behaves the same on any stream socket.
+    let (socket, _peer) =
+        std::os::unix::net::UnixStream::pair().expect("unable to create socket pair");
+    let stream = VsockStream::from(OwnedFd::from(socket));
+
+    stream
+        .set_linger(Some(Duration::from_millis(500)))
+        .expect("SO_LINGER failed");
+    assert_eq!(
+        stream.linger().expect("SO_LINGER failed"),
+        Some(Duration::from_secs(1))
+    );
+    stream
+        .set_linger(Some(Duration::from_millis(1500)))
+        .expect("SO_LINGER failed");
+    assert_eq!(
+        stream.linger().expect("SO_LINGER failed"),
+        Some(Duration::from_secs(2))
+    );
+    stream.set_linger(None).expect("SO_LINGER failed");
+    assert_eq!(stream.linger().expect("SO_LINGER failed"), None);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn send_zerocopy_releases_buffers() {
+    use std::time::Duration;
+
+    const PORT: u32 = 8014;
+    const LEN: usize = 1024 * 1024;
+
+    let listener = VsockListener::bind(VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT))
+        .expect("unable to bind vsock listener");
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let (client, server) = tokio::join!(VsockStream::connect(addr), listener.accept());
+    let mut client = client.expect("connection failed");
+    let (mut server, _) = server.expect("accept failed");
+
+    let data: Vec<u8> = (0..LEN).map(|i| i as u8).collect();
+    let reader = tokio::spawn(async move {
+        let mut received = vec![0u8; LEN];
+        server.read_exact(&mut received).await.expect("read failed");
+        received
+    });
+    client
+        .send_zerocopy(bytes::Bytes::from(data.clone()))
+        .await
+        .expect("send failed");
+    tokio::time::timeout(Duration::from_secs(5), client.zerocopy_completed())
+        .await
+        .expect("completions were not reported")
+        .expect("waiting for completions failed");
+    assert_eq!(client.zerocopy_pending(), 0);
+    assert!(reader.await.expect("reader panicked") == data);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn file_transfers_check_offsets_and_timeouts() {
+    use std::fs::File;
+    use std::io::ErrorKind;
+    use std::os::fd::OwnedFd;
+    use std::time::Duration;
+
+    let (a, _b) = std::os::unix::net::UnixStream::pair().expect("unable to create socket pair");
+    let stream = VsockStream::from(OwnedFd::from(a));
+    let path = std::env::temp_dir().join(format!("tokio-vsock-offsets-{}", std::process::id()));
+    let file = File::create(&path).expect("unable to create file");
+
+    let err = stream.send_file(&file, u64::MAX, 1).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    let err = stream.recv_to_file(&file, u64::MAX, 1).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    // Nothing arrives, so the read timeout ends the transfer.
+    stream.set_read_timeout(Some(Duration::from_millis(100)));
+    let err = tokio::time::timeout(Duration::from_secs(5), stream.recv_to_file(&file, 0, 1))
+        .await
+        .expect("read timeout was not enforced")
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+#[cfg(target_os = "linux")]
+async fn send_file_and_recv_to_file() {
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    const PORT: u32 = 8015;
+    const LEN: usize = 300 * 1024;
+
+    let dir = std::env::temp_dir();
+    let source_path = dir.join(format!("tokio-vsock-send-{}", std::process::id()));
+    let target_path = dir.join(format!("tokio-vsock-recv-{}", std::process::id()));
+    let data: Vec<u8> = (0..LEN).map(|i| (i % 251) as u8).collect();
+    File::create(&source_path)
+        .and_then(|mut file| file.write_all(&data))
+        .expect("unable to write source file");
+    let source = File::open(&source_path).expect("unable to open source file");
+    let target = File::create(&target_path).expect("unable to create target file");
+
+    let listener = VsockListener::bind(VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT))
+        .expect("unable to bind vsock listener");
+    let addr = VsockAddr::new(tokio_vsock::VMADDR_CID_LOCAL, PORT);
+    let (client, server) = tokio::join!(VsockStream::connect(addr), listener.accept());
+    let client = client.expect("connection failed");
+    let (server, _) = server.expect("accept failed");
+
+    // Skip the first kilobyte, and ask for more than the file holds.
+    let receiver = tokio::spawn(async move {
+        server
+            .recv_to_file(&target, 16, LEN as u64)
+            .await
+            .expect("receive failed")
+    });
+    let sent = client
+        .send_file(&source, 1024, LEN as u64)
+        .await
+        .expect("send failed");
+    assert_eq!(sent, (LEN - 1024) as u64);
+    client
+        .shutdown(std::net::Shutdown::Write)
+        .expect("shutdown failed");
+    assert_eq!(receiver.await.expect("receiver panicked"), sent);
+
+    let received = fs::read(&target_path).expect("unable to read target file");
+    assert_eq!(&received[16..], &data[1024..]);
+    let _ = fs::remove_file(source_path);
+    let _ = fs::remove_file(target_path);
+}